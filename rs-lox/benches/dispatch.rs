@@ -0,0 +1,196 @@
+//! `cargo bench --bench dispatch [-- files..]` compares the ways the VM can read its code.
+//!
+//! For every workload (the built in loops, or the given files) it reports the code size as bytes
+//! and as the old one-enum-per-op layout, and the time per op to walk the instruction stream with
+//! the checked decoder, with the one the VM uses on verified chunks and over a `Vec<OpCode>`.
+//! `run` is a whole `rs-lox -e` process, startup included. `vm ns/op` is that run without the
+//! startup, over the number of ops the VM executed, as counted by `--profile`.
+//! Times are the best of a few runs, the machine noise only ever makes things slower.
+//!
+//! The enum VM is gone, so there is nothing to race `vm ns/op` against. The walk columns are the
+//! difference between the layouts: the byte stream is less than half the size, but decoding it
+//! is not faster than reading an enum. The win is size, not dispatch speed.
+use std::hint::black_box;
+use std::mem::size_of;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use compiler::{CodeGen, Parser};
+use lang::{OpCode, Scanner};
+use values::Chunk;
+
+const RUNS: usize = 5;
+/// how many times the instruction stream is walked per run
+const WALKS: usize = 10_000;
+
+const WORKLOADS: [(&str, &str); 3] = [
+    (
+        "for loop",
+        "var sum = 0;
+for (var i = 0; i < 200000; i = i + 1) {
+    var j = i * 2;
+    if (j > 10) sum = sum + 1; else sum = sum - 1;
+}",
+    ),
+    (
+        "while loop",
+        "var i = 0;
+while (i < 200000) i = i + 1;",
+    ),
+    (
+        "locals",
+        "{
+    var a = 0;
+    for (var i = 0; i < 200000; i = i + 1) {
+        var b = i;
+        a = a + b - 1;
+    }
+}",
+    ),
+];
+
+fn main() {
+    // cargo passes `--bench`, everything else is a file to measure
+    let files: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    let workloads: Vec<(String, String)> = if files.is_empty() {
+        WORKLOADS
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    } else {
+        files
+            .into_iter()
+            .map(|path| {
+                let source =
+                    fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+                (path, source)
+            })
+            .collect()
+    };
+
+    println!(
+        " {:<16} {:>6} {:>8} {:>11} {:>13} {:>14} {:>11} {:>10} {:>10}",
+        "workload",
+        "ops",
+        "bytes",
+        "enum bytes",
+        "checked ns/op",
+        "verified ns/op",
+        "enum ns/op",
+        "run",
+        "vm ns/op"
+    );
+    let startup = best_of(|| rs_lox(&["-e", ""]));
+    for (name, source) in workloads {
+        let chunk = compile(&source).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        chunk.verify().unwrap();
+        let ops: Vec<OpCode> = chunk.instructions().map(|(_, op)| op).collect();
+        let per_op = |d: Duration| d.as_nanos() as f64 / (ops.len() * WALKS) as f64;
+
+        let checked = best_of(|| walk_checked(&chunk));
+        let verified = best_of(|| walk_verified(&chunk));
+        let walk_enum = best_of(|| walk_enum(&ops));
+        let run = best_of(|| rs_lox(&["-e", &source]));
+        let executed = executed_ops(&source);
+        let vm = run.saturating_sub(startup);
+
+        println!(
+            " {:<16} {:>6} {:>8} {:>11} {:>13.2} {:>14.2} {:>11.2} {:>8.1}ms {:>10.2}",
+            name,
+            ops.len(),
+            chunk.count(),
+            ops.len() * size_of::<OpCode>(),
+            per_op(checked),
+            per_op(verified),
+            per_op(walk_enum),
+            run.as_secs_f64() * 1000.0,
+            vm.as_nanos() as f64 / executed as f64
+        );
+    }
+}
+
+fn compile(source: &str) -> compiler::COMPError<Chunk> {
+    let mut scanner = Scanner::from_str(source)?;
+    let program = Parser::init(&mut scanner).parse()?;
+    let mut chunk = Chunk::new();
+    CodeGen::init(&mut chunk).generate(&program)?;
+    Ok(chunk)
+}
+
+fn rs_lox(args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_rs-lox"))
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "rs-lox {:?} failed", args);
+}
+
+/// How many ops a run of `source` executes, from the first line of the `--profile` report
+fn executed_ops(source: &str) -> u64 {
+    let folded = env::temp_dir().join("rs-lox-dispatch-bench.folded");
+    let out = Command::new(env!("CARGO_BIN_EXE_rs-lox"))
+        .arg(format!("--profile={}", folded.display()))
+        .args(["-e", source])
+        .stdout(Stdio::null())
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&folded);
+    let report = String::from_utf8_lossy(&out.stderr);
+    // profile: 0.123456s wall, 1234 instructions
+    report
+        .lines()
+        .next()
+        .and_then(|line| line.split(", ").nth(1))
+        .and_then(|count| count.split(' ').next())
+        .and_then(|count| count.parse().ok())
+        .unwrap_or_else(|| panic!("no instruction count in {:?}", report))
+}
+
+fn best_of(mut f: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// Decode every op with [`Chunk::decode_op`], which checks everything it reads
+fn walk_checked(chunk: &Chunk) {
+    for _ in 0..WALKS {
+        let mut ip = 0;
+        while let Some((op, len)) = chunk.decode_op(ip) {
+            black_box(op);
+            ip += len;
+        }
+    }
+}
+
+/// The walk the VM does, [`Chunk::fetch_op`] until the final RETURN
+fn walk_verified(chunk: &Chunk) {
+    let end = chunk.count();
+    for _ in 0..WALKS {
+        let mut ip = 0;
+        while ip < end {
+            let (op, len) = chunk.fetch_op(ip);
+            black_box(op);
+            ip += len;
+        }
+    }
+}
+
+/// The same walk over a `Vec<OpCode>`, which is how chunks used to store their code
+fn walk_enum(ops: &[OpCode]) {
+    for _ in 0..WALKS {
+        for op in ops.iter() {
+            black_box(*op);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Name resolution on the AST, for tooling (the language server and friends).
+//!
+//! Scopes are tracked with the same [`Compiler`] the code generator uses, so a name
+//! resolves here to the same variable it resolves to in the bytecode.
+use std::collections::HashMap;
+
+use crate::ast::{Expr, ExprKind, Ident, Program, Span, Stmt, StmtKind};
+use crate::Compiler;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Global,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// the name in the declaration
+    pub decl: Span,
+    /// the whole `var` statement
+    pub stmt: Span,
+    /// where the name can be used. globals are late bound, so they are visible everywhere
+    pub visible: Span,
+}
+
+/// Use of a name. `symbol` indexes [`Analysis::symbols`], `None` for globals that are never declared
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub symbol: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+impl Analysis {
+    pub fn of(program: &Program) -> Self {
+        let end = program.decls.last().map(|s| s.span.end).unwrap_or(0);
+        let mut resolver = Resolver {
+            compiler: Compiler::init(),
+            slots: vec![],
+            globals: HashMap::new(),
+            unresolved: vec![],
+            out: Analysis::default(),
+            end,
+        };
+        for stmt in program.decls.iter() {
+            resolver.stmt(stmt);
+        }
+        resolver.finish()
+    }
+
+    /// Symbol declared or used at `pos`
+    pub fn symbol_at(&self, pos: usize) -> Option<&Symbol> {
+        if let Some(sym) = self.symbols.iter().find(|s| s.decl.contains(pos)) {
+            return Some(sym);
+        }
+        self.references
+            .iter()
+            .find(|r| r.span.contains(pos))
+            .and_then(|r| r.symbol)
+            .map(|idx| &self.symbols[idx])
+    }
+
+    /// Everything that can be named at `pos`, inner locals shadow outer names
+    pub fn visible_at(&self, pos: usize) -> Vec<&Symbol> {
+        let mut seen: Vec<&Symbol> = vec![];
+        let locals = self
+            .symbols
+            .iter()
+            .rev()
+            .filter(|s| s.kind == SymbolKind::Local && s.visible.contains(pos));
+        let globals = self.symbols.iter().filter(|s| s.kind == SymbolKind::Global);
+        for sym in locals.chain(globals) {
+            if !seen.iter().any(|s| s.name == sym.name) {
+                seen.push(sym);
+            }
+        }
+        seen
+    }
+}
+
+struct Resolver {
+    compiler: Compiler,
+    /// symbol index for every local slot the compiler handed out
+    slots: Vec<usize>,
+    /// first declaration of every global
+    globals: HashMap<String, usize>,
+    /// global uses, resolved once all declarations are known
+    unresolved: Vec<(usize, String)>,
+    out: Analysis,
+    /// end of the program, globals are visible until here
+    end: usize,
+}
+
+impl Resolver {
+    fn finish(mut self) -> Analysis {
+        for (idx, name) in self.unresolved {
+            self.out.references[idx].symbol = self.globals.get(&name).copied();
+        }
+        for sym in self.out.symbols.iter_mut() {
+            if sym.kind == SymbolKind::Global {
+                sym.visible = Span::new(0, self.end, 1);
+            }
+        }
+        self.out
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Var { name, init } => {
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                self.declare(name, stmt.span);
+            }
+            StmtKind::Print(expr) | StmtKind::Expression(expr) | StmtKind::Assert(expr) => {
+                self.expr(expr)
+            }
+            StmtKind::AssertEq(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            StmtKind::Test { body, .. } => self.stmt(body),
+            StmtKind::Block(stmts) => {
+                self.compiler.begin_scope();
+                for s in stmts {
+                    self.stmt(s);
+                }
+                self.end_scope(stmt.span.end);
+            }
+            StmtKind::If { cond, then_, else_ } => {
+                self.expr(cond);
+                self.stmt(then_);
+                if let Some(else_) = else_ {
+                    self.stmt(else_);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                incr,
+                body,
+            } => {
+                self.compiler.begin_scope();
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                for expr in cond.iter().chain(incr.iter()) {
+                    self.expr(expr);
+                }
+                self.stmt(body);
+                self.end_scope(stmt.span.end);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Str(_) | ExprKind::Bool(_) | ExprKind::Nil => {}
+            ExprKind::Variable(name) => self.reference(name),
+            ExprKind::Assign { name, value } => {
+                self.reference(name);
+                self.expr(value);
+            }
+            ExprKind::Grouping(inner) | ExprKind::Unary { rhs: inner, .. } => self.expr(inner),
+            ExprKind::Binary { lhs, rhs, .. }
+            | ExprKind::Logical { lhs, rhs, .. }
+            | ExprKind::Index {
+                target: lhs,
+                index: rhs,
+            } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Call { callee, args }
+            | ExprKind::Invoke {
+                target: callee,
+                args,
+                ..
+            } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Ident, stmt: Span) {
+        let idx = self.out.symbols.len();
+        let kind = if self.compiler.local_scope() {
+            self.compiler.add_local(name.name.clone());
+            self.slots.push(idx);
+            SymbolKind::Local
+        } else {
+            self.globals.entry(name.name.clone()).or_insert(idx);
+            SymbolKind::Global
+        };
+        self.out.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            decl: name.span,
+            stmt,
+            // locals get the end of their scope once we leave it
+            visible: Span::new(stmt.end, self.end, stmt.line),
+        });
+    }
+
+    fn reference(&mut self, name: &Ident) {
+        let symbol = self
+            .compiler
+            .find_local(&name.name)
+            .map(|slot| self.slots[slot as usize]);
+        if symbol.is_none() {
+            self.unresolved
+                .push((self.out.references.len(), name.name.clone()));
+        }
+        self.out.references.push(Reference {
+            span: name.span,
+            symbol,
+        });
+    }
+
+    fn end_scope(&mut self, end: usize) {
+        while self.compiler.should_pop_local() {
+            if let Some(idx) = self.slots.pop() {
+                self.out.symbols[idx].visible.end = end;
+            }
+        }
+        self.compiler.end_scope();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+    use lang::Scanner;
+
+    fn analyse(source: &str) -> Analysis {
+        let mut scanner = Scanner::from_str(source).unwrap();
+        Analysis::of(&Parser::init(&mut scanner).parse().unwrap())
+    }
+
+    fn names(syms: Vec<&Symbol>) -> Vec<&str> {
+        syms.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn scopes_and_shadowing() {
+        let source = "var a = 1; { var b = a; { var a = b; print a; } print b; } print c;";
+        let an = analyse(source);
+        let inner_print = source.find("print a").unwrap();
+        assert_eq!(names(an.visible_at(inner_print)), ["a", "b"]);
+        assert_eq!(an.visible_at(inner_print)[0].kind, SymbolKind::Local);
+        assert_eq!(
+            names(an.visible_at(source.find("print b").unwrap())),
+            ["b", "a"]
+        );
+        assert_eq!(names(an.visible_at(source.find("print c").unwrap())), ["a"]);
+
+        // `var b = a` reads the global, `print a` the inner local
+        let outer_a = an.symbol_at(source.find("= a").unwrap() + 2).unwrap();
+        assert_eq!(outer_a.kind, SymbolKind::Global);
+        let inner_a = an.symbol_at(inner_print + 6).unwrap();
+        assert_eq!(&source[inner_a.stmt.start..inner_a.stmt.end], "var a = b;");
+        assert!(an.symbol_at(source.find("c;").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt::{self, Write};
+
+/// Location of a node in the source.
+/// `start` and `end` are absolute char offsets, `line` and `col` are where the node starts (1 based).
+/// A `col` of 0 means the column is not known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: u32) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            col: 0,
+        }
+    }
+
+    pub fn with_col(self, col: u32) -> Self {
+        Self { col, ..self }
+    }
+
+    /// Span that covers both spans, line and column are taken from the first one
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            ..self
+        }
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos <= self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+/// `and` and `or` get their own op type since they short circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(f32),
+    Str(String),
+    Bool(bool),
+    Nil,
+    Variable(Ident),
+    Assign {
+        name: Ident,
+        value: Box<Expr>,
+    },
+    Grouping(Box<Expr>),
+    Unary {
+        op: UnaryOp,
+        rhs: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Logical {
+        op: LogicalOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    /// `target[index]`
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `target.name(args..)`
+    Invoke {
+        target: Box<Expr>,
+        name: Ident,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Var {
+        name: Ident,
+        init: Option<Expr>,
+    },
+    Print(Expr),
+    Expression(Expr),
+    Block(Vec<Stmt>),
+    If {
+        cond: Expr,
+        then_: Box<Stmt>,
+        else_: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    For {
+        init: Option<Box<Stmt>>,
+        cond: Option<Expr>,
+        incr: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    /// `test "name" { .. }`, only `rs-lox test` runs those
+    Test {
+        name: String,
+        body: Box<Stmt>,
+    },
+    Assert(Expr),
+    AssertEq(Expr, Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+/// Root of the tree, a list of top level declarations
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub decls: Vec<Stmt>,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryOp::*;
+        let txt = match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Equal => "==",
+            NotEqual => "!=",
+            Greater => ">",
+            GreaterEqual => ">=",
+            Less => "<",
+            LessEqual => "<=",
+        };
+        write!(f, "{}", txt)
+    }
+}
+
+impl fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogicalOp::And => write!(f, "and"),
+            LogicalOp::Or => write!(f, "or"),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}[{}..{}]", self.line, self.start, self.end)
+    }
+}
+
+/// Indented tree dump, one node per line. This is what `--dump-ast` prints
+pub struct AstDump<'a>(pub &'a Program);
+
+impl<'a> fmt::Display for AstDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        out.push_str("Program\n");
+        for stmt in self.0.decls.iter() {
+            dump_stmt(&mut out, stmt, 1)?;
+        }
+        write!(f, "{}", out)
+    }
+}
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+fn dump_stmt(out: &mut String, stmt: &Stmt, depth: usize) -> fmt::Result {
+    indent(out, depth);
+    match &stmt.kind {
+        StmtKind::Var { name, init } => {
+            writeln!(out, "Var {} {}", name.name, stmt.span)?;
+            if let Some(e) = init {
+                dump_expr(out, e, depth + 1)?;
+            }
+        }
+        StmtKind::Print(e) => {
+            writeln!(out, "Print {}", stmt.span)?;
+            dump_expr(out, e, depth + 1)?;
+        }
+        StmtKind::Expression(e) => {
+            writeln!(out, "Expression {}", stmt.span)?;
+            dump_expr(out, e, depth + 1)?;
+        }
+        StmtKind::Block(stmts) => {
+            writeln!(out, "Block {}", stmt.span)?;
+            for s in stmts {
+                dump_stmt(out, s, depth + 1)?;
+            }
+        }
+        StmtKind::If { cond, then_, else_ } => {
+            writeln!(out, "If {}", stmt.span)?;
+            dump_expr(out, cond, depth + 1)?;
+            dump_stmt(out, then_, depth + 1)?;
+            if let Some(s) = else_ {
+                dump_stmt(out, s, depth + 1)?;
+            }
+        }
+        StmtKind::While { cond, body } => {
+            writeln!(out, "While {}", stmt.span)?;
+            dump_expr(out, cond, depth + 1)?;
+            dump_stmt(out, body, depth + 1)?;
+        }
+        StmtKind::For {
+            init,
+            cond,
+            incr,
+            body,
+        } => {
+            writeln!(out, "For {}", stmt.span)?;
+            if let Some(s) = init {
+                dump_stmt(out, s, depth + 1)?;
+            }
+            if let Some(e) = cond {
+                dump_expr(out, e, depth + 1)?;
+            }
+            if let Some(e) = incr {
+                dump_expr(out, e, depth + 1)?;
+            }
+            dump_stmt(out, body, depth + 1)?;
+        }
+        StmtKind::Test { name, body } => {
+            writeln!(out, "Test {:?} {}", name, stmt.span)?;
+            dump_stmt(out, body, depth + 1)?;
+        }
+        StmtKind::Assert(e) => {
+            writeln!(out, "Assert {}", stmt.span)?;
+            dump_expr(out, e, depth + 1)?;
+        }
+        StmtKind::AssertEq(lhs, rhs) => {
+            writeln!(out, "AssertEq {}", stmt.span)?;
+            dump_expr(out, lhs, depth + 1)?;
+            dump_expr(out, rhs, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn dump_expr(out: &mut String, expr: &Expr, depth: usize) -> fmt::Result {
+    indent(out, depth);
+    match &expr.kind {
+        ExprKind::Number(n) => writeln!(out, "Number {} {}", n, expr.span)?,
+        ExprKind::Str(s) => writeln!(out, "Str {:?} {}", s, expr.span)?,
+        ExprKind::Bool(b) => writeln!(out, "Bool {} {}", b, expr.span)?,
+        ExprKind::Nil => writeln!(out, "Nil {}", expr.span)?,
+        ExprKind::Variable(ident_) => writeln!(out, "Variable {} {}", ident_.name, expr.span)?,
+        ExprKind::Assign { name, value } => {
+            writeln!(out, "Assign {} {}", name.name, expr.span)?;
+            dump_expr(out, value, depth + 1)?;
+        }
+        ExprKind::Grouping(e) => {
+            writeln!(out, "Grouping {}", expr.span)?;
+            dump_expr(out, e, depth + 1)?;
+        }
+        ExprKind::Unary { op, rhs } => {
+            writeln!(out, "Unary {} {}", op, expr.span)?;
+            dump_expr(out, rhs, depth + 1)?;
+        }
+        ExprKind::Binary { op, lhs, rhs } => {
+            writeln!(out, "Binary {} {}", op, expr.span)?;
+            dump_expr(out, lhs, depth + 1)?;
+            dump_expr(out, rhs, depth + 1)?;
+        }
+        ExprKind::Logical { op, lhs, rhs } => {
+            writeln!(out, "Logical {} {}", op, expr.span)?;
+            dump_expr(out, lhs, depth + 1)?;
+            dump_expr(out, rhs, depth + 1)?;
+        }
+        ExprKind::Call { callee, args } => {
+            writeln!(out, "Call {}", expr.span)?;
+            dump_expr(out, callee, depth + 1)?;
+            for arg in args {
+                dump_expr(out, arg, depth + 1)?;
+            }
+        }
+        ExprKind::Index { target, index } => {
+            writeln!(out, "Index {}", expr.span)?;
+            dump_expr(out, target, depth + 1)?;
+            dump_expr(out, index, depth + 1)?;
+        }
+        ExprKind::Invoke { target, name, args } => {
+            writeln!(out, "Invoke {} {}", name.name, expr.span)?;
+            dump_expr(out, target, depth + 1)?;
+            for arg in args {
+                dump_expr(out, arg, depth + 1)?;
+            }
+        }
+    }
+    Ok(())
+}
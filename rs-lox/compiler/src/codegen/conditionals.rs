@@ -0,0 +1,111 @@
+use super::*;
+
+impl<'a> CodeGen<'a> {
+    pub(super) fn for_(
+        &mut self,
+        init: Option<&Stmt>,
+        cond: Option<&Expr>,
+        incr: Option<&Expr>,
+        body: &Stmt,
+    ) -> COMPError<()> {
+        self.compiler.begin_scope();
+
+        let mut to_loop_body: Vec<usize> = vec![];
+        let mut to_loop_end: Vec<usize> = vec![];
+
+        // no initializer means nothing to emit here
+        if let Some(init) = init {
+            self.stmt(init)?;
+        }
+        let before_cond = self.here()?;
+
+        if let Some(cond) = cond {
+            self.expression(cond)?;
+
+            // jump to the end of the loop
+            to_loop_end.push(self.emit_jump(OpCode::JUMP_IF_FALSE(0xFF)));
+            // no false, we get rid of the conditoinal (if we jump we will get rid of it in loop closure)
+            self.emit_op(OpCode::POP);
+
+            to_loop_body.push(self.emit_jump(OpCode::JUMP(0xFF)));
+        }
+
+        // if there is no increase clause this thing will be the same as loop body
+        let mut inc_clause = before_cond;
+
+        // increment clause. this one is tricky.
+        // we emit it here, but it must run after the body is executed
+        if let Some(incr) = incr {
+            to_loop_body.push(self.emit_jump(OpCode::JUMP(0xFF)));
+
+            inc_clause = self.here()?;
+
+            self.expression(incr)?;
+            self.emit_op(OpCode::POP);
+            // this happens only if we have increase clause
+            self.emit_op(OpCode::JUMP(before_cond));
+        }
+
+        let loop_body = self.here()?;
+        self.patch_jumps(OpCode::JUMP(loop_body), &to_loop_body);
+        self.stmt(body)?;
+        self.emit_op(OpCode::JUMP(inc_clause));
+        let loop_end = self.here()?;
+        self.patch_jumps(OpCode::JUMP_IF_FALSE(loop_end), &to_loop_end);
+        // the conditional is still on the stack when we jump out. no condition, nothing to clean
+        if cond.is_some() {
+            self.emit_op(OpCode::POP);
+        }
+        self.clean_locals();
+        self.compiler.end_scope();
+        Ok(())
+    }
+
+    pub(super) fn while_(&mut self, cond: &Expr, body: &Stmt) -> COMPError<()> {
+        let loop_start = self.here()?;
+        self.expression(cond)?;
+        // at this point we have some result on the stack
+
+        // if rhis result is flase we jumpt to the end of the loop
+        let jmp_addr = self.emit_jump(OpCode::JUMP_IF_FALSE(0xFFFF));
+        // throw away the old loop condition from the stack
+        self.emit_op(OpCode::POP);
+        self.stmt(body)?;
+        self.emit_op(OpCode::JUMP(loop_start));
+
+        let end_loop = self.here()?;
+        self.patch_jumps(OpCode::JUMP_IF_FALSE(end_loop), &[jmp_addr]);
+
+        // in case we jumped to the end, we need to pop whatever we had in there
+        self.emit_op(OpCode::POP);
+        Ok(())
+    }
+
+    pub(super) fn if_else(
+        &mut self,
+        cond: &Expr,
+        then_: &Stmt,
+        else_: Option<&Stmt>,
+    ) -> COMPError<()> {
+        self.expression(cond)?;
+
+        // the condition stays on the stack after the jump, both branches start by popping it
+        let to_false = self.emit_jump(OpCode::JUMP_IF_FALSE(0xFFFF));
+        self.emit_op(OpCode::POP);
+        self.stmt(then_)?;
+        // skip the false branch
+        let to_end = self.emit_jump(OpCode::JUMP(0xFFFF));
+
+        let false_branch = self.here()?;
+        self.patch_jumps(OpCode::JUMP_IF_FALSE(false_branch), &[to_false]);
+        self.span = cond.span;
+        self.emit_op(OpCode::POP);
+        if let Some(else_) = else_ {
+            self.stmt(else_)?;
+        }
+
+        let end = self.here()?;
+        self.patch_jumps(OpCode::JUMP(end), &[to_end]);
+        Ok(())
+    }
+}
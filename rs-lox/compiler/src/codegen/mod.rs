@@ -0,0 +1,247 @@
+use crate::ast::{Expr, ExprKind, Ident, Program, Span, Stmt, StmtKind};
+use crate::{COMPError, Compiler};
+
+use lang::CompileError;
+use lang::{ConstIdx, InstructAddr, OpCode};
+use values::{Chunk, SourcePos, Value};
+
+mod conditionals;
+mod ops;
+
+/// Walks the AST and writes bytecode into a chunk.
+/// Local variable resolution happens here, the [`Compiler`] keeps track of scopes and stack slots.
+pub struct CodeGen<'a> {
+    chunk: &'a mut Chunk,
+    compiler: Compiler,
+    /// debug info index of every local the compiler holds, by slot
+    debug_locals: Vec<usize>,
+    /// span of the node we are currently emitting, it goes into the source map
+    span: Span,
+}
+
+impl<'a> CodeGen<'a> {
+    pub fn init(chunk: &'a mut Chunk) -> Self {
+        Self {
+            chunk,
+            compiler: Compiler::init(),
+            debug_locals: vec![],
+            span: Span::default(),
+        }
+    }
+
+    /// Generator for code that runs while `locals` (ordered by slot) are already on the stack.
+    /// The debugger uses this to evaluate expressions in a paused program
+    pub fn with_locals(chunk: &'a mut Chunk, locals: &[&str]) -> Self {
+        let mut codegen = Self::init(chunk);
+        codegen.compiler.begin_scope();
+        for name in locals {
+            codegen.compiler.add_local(name.to_string());
+        }
+        codegen
+    }
+
+    /// A single expression, its value is left on the stack
+    pub fn generate_expr(&mut self, expr: &Expr) -> COMPError<()> {
+        self.span = expr.span;
+        self.expression(expr)?;
+        self.emit_op(OpCode::RETURN);
+        Ok(())
+    }
+
+    pub fn generate(&mut self, program: &Program) -> COMPError<()> {
+        for decl in program.decls.iter() {
+            self.stmt(decl)?;
+        }
+        self.emit_op(OpCode::RETURN);
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> COMPError<()> {
+        self.span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Var { name, init } => self.var_declaration(name, init.as_ref())?,
+            StmtKind::Print(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::PRINT);
+            }
+            StmtKind::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::POP);
+            }
+            StmtKind::Block(stmts) => self.scope(stmts)?,
+            StmtKind::If { cond, then_, else_ } => self.if_else(cond, then_, else_.as_deref())?,
+            StmtKind::While { cond, body } => self.while_(cond, body)?,
+            StmtKind::For {
+                init,
+                cond,
+                incr,
+                body,
+            } => self.for_(init.as_deref(), cond.as_ref(), incr.as_ref(), body)?,
+            // tests are picked out and compiled on their own by the test runner
+            StmtKind::Test { .. } => {}
+            StmtKind::Assert(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::ASSERT);
+            }
+            StmtKind::AssertEq(lhs, rhs) => {
+                self.expression(lhs)?;
+                self.expression(rhs)?;
+                self.emit_op(OpCode::ASSERT_EQ);
+            }
+        }
+        Ok(())
+    }
+
+    fn scope(&mut self, stmts: &[Stmt]) -> COMPError<()> {
+        self.compiler.begin_scope();
+        for s in stmts {
+            self.stmt(s)?;
+        }
+
+        self.clean_locals();
+        self.compiler.end_scope();
+
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> COMPError<()> {
+        // ops that come after the operands (like the ADD of `a + b`) belong to the whole expression
+        let outer = std::mem::replace(&mut self.span, expr.span);
+        match &expr.kind {
+            ExprKind::Number(num) => {
+                let const_idx = self.make_const((*num).into())?;
+                self.emit_op(OpCode::CONSTANT(const_idx));
+            }
+            ExprKind::Str(s) => {
+                let const_idx = self.make_const(Value::String(s.clone()))?;
+                self.emit_op(OpCode::CONSTANT(const_idx));
+            }
+            ExprKind::Bool(true) => self.emit_op(OpCode::TRUE),
+            ExprKind::Bool(false) => self.emit_op(OpCode::FALSE),
+            ExprKind::Nil => self.emit_op(OpCode::NIL),
+            ExprKind::Grouping(inner) => self.expression(inner)?,
+            ExprKind::Variable(ident_) => self.get_variable(ident_)?,
+            ExprKind::Assign { name, value } => self.set_variable(name, value)?,
+            ExprKind::Unary { op, rhs } => self.unary(*op, rhs)?,
+            ExprKind::Binary { op, lhs, rhs } => self.binary(*op, lhs, rhs)?,
+            ExprKind::Logical { op, lhs, rhs } => self.logical(*op, lhs, rhs)?,
+            ExprKind::Call { callee, args } => self.call(callee, args)?,
+            ExprKind::Invoke { target, name, args } => self.invoke(target, name, args)?,
+            ExprKind::Index { target, index } => {
+                self.expression(target)?;
+                self.expression(index)?;
+                self.emit_op(OpCode::INDEX);
+            }
+        }
+        self.span = outer;
+        Ok(())
+    }
+
+    fn var_declaration(&mut self, name: &Ident, init: Option<&Expr>) -> COMPError<()> {
+        match init {
+            // if we have an expresion that initializes the var, calculate it and put on stack
+            Some(expr) => self.expression(expr)?,
+            // we just made room on out var table for this one and push NIL inside
+            None => self.emit_op(OpCode::NIL),
+        }
+
+        if self.compiler.local_scope() {
+            if self.compiler.local_exists(&name.name).is_none() {
+                let span = name.span.start..name.span.end;
+                return Err(CompileError::Redeclared(name.name.clone(), span));
+            }
+            let slot = self.compiler.add_local(name.name.clone());
+            let idx = self.chunk.open_local(name.name.clone(), slot);
+            self.debug_locals.push(idx);
+            // at this point the variable is already on the stack and is going to be used in the scope
+            // it was deined in (or deeper scope)
+        } else {
+            let const_idx = self.make_const(Value::String(name.name.clone()))?;
+            self.emit_op(OpCode::DEFINE_GLOBAL(const_idx));
+        }
+        Ok(())
+    }
+
+    fn get_variable(&mut self, ident_: &Ident) -> COMPError<()> {
+        match self.compiler.find_local(&ident_.name) {
+            Some(slot) => self.emit_op(OpCode::GET_LOCAL(slot)),
+            None => {
+                let ident_idx = self.make_const(Value::String(ident_.name.clone()))?;
+                self.emit_op(OpCode::GET_GLOBAL(ident_idx))
+            }
+        }
+        Ok(())
+    }
+
+    fn set_variable(&mut self, ident_: &Ident, value: &Expr) -> COMPError<()> {
+        let is_local = self.compiler.find_local(&ident_.name);
+        self.expression(value)?;
+        match is_local {
+            Some(slot) => self.emit_op(OpCode::SET_LOCAL(slot)),
+            None => {
+                let ident_idx = self.make_const(Value::String(ident_.name.clone()))?;
+                self.emit_op(OpCode::SET_GLOBAL(ident_idx))
+            }
+        }
+        Ok(())
+    }
+
+    fn make_const(&mut self, val: Value) -> COMPError<ConstIdx> {
+        let const_idx = self.chunk.add_const(val);
+        if const_idx > (u8::MAX - 1) as usize {
+            return Err(CompileError::ToManyConstants);
+        }
+        Ok(const_idx as ConstIdx)
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        let span = self.span;
+        let len = span.end.saturating_sub(span.start) as u32;
+        self.chunk
+            .add_op_at(op, SourcePos::new(span.line, span.col, len));
+    }
+
+    /// emit a jump with a placeholder address, returns the address to patch later
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        let addr = self.chunk.count();
+        self.emit_op(op);
+        addr
+    }
+
+    /// Point the placeholder jumps at `ips` to the next op with `op`
+    fn patch_jumps(&mut self, op: OpCode, ips: &[usize]) {
+        self.chunk.patch_multip_op(op, ips);
+        // the verifier can't tell a wrapped address from a real one, so check it round trips
+        let target = self.chunk.count();
+        for ip in ips {
+            debug_assert!(
+                matches!(
+                    self.chunk.read_op(*ip),
+                    Some(OpCode::JUMP(to) | OpCode::JUMP_IF_FALSE(to)) if to as usize == target
+                ),
+                "jump at {} doesn't land on {}",
+                ip,
+                target
+            );
+        }
+    }
+
+    fn clean_locals(&mut self) {
+        while self.compiler.should_pop_local() {
+            if let Some(idx) = self.debug_locals.pop() {
+                self.chunk.close_local(idx);
+            }
+            self.emit_op(OpCode::POP);
+        }
+    }
+
+    /// Address of the next op, as a jump operand
+    fn here(&self) -> COMPError<InstructAddr> {
+        jump_addr(self.chunk.count())
+    }
+}
+
+/// Jump operands are an [`InstructAddr`], code past that can't be jumped to
+fn jump_addr(ip: usize) -> COMPError<InstructAddr> {
+    InstructAddr::try_from(ip).map_err(|_| CompileError::JumpTooLarge)
+}
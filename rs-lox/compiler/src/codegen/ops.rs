@@ -0,0 +1,100 @@
+use super::*;
+
+use crate::ast::{BinaryOp, LogicalOp, UnaryOp};
+
+impl<'a> CodeGen<'a> {
+    pub(super) fn unary(&mut self, op: UnaryOp, rhs: &Expr) -> COMPError<()> {
+        self.expression(rhs)?;
+
+        match op {
+            UnaryOp::Neg => self.emit_op(OpCode::NEGATE),
+            UnaryOp::Not => self.emit_op(OpCode::NOT),
+        };
+
+        Ok(())
+    }
+
+    pub(super) fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> COMPError<()> {
+        // put both sides on the stack and do the binary operation on both the values in the stack.
+        self.expression(lhs)?;
+        self.expression(rhs)?;
+
+        // apply the binary op on both expressions
+        match op {
+            BinaryOp::Add => self.emit_op(OpCode::ADD),
+            BinaryOp::Sub => self.emit_op(OpCode::SUB),
+            BinaryOp::Mul => self.emit_op(OpCode::MUL),
+            BinaryOp::Div => self.emit_op(OpCode::DIV),
+            BinaryOp::Equal => self.emit_op(OpCode::EQUAL),
+            BinaryOp::NotEqual => {
+                self.emit_op(OpCode::EQUAL);
+                self.emit_op(OpCode::NOT);
+            }
+            BinaryOp::Greater => self.emit_op(OpCode::GREATER),
+            BinaryOp::GreaterEqual => {
+                self.emit_op(OpCode::LESS);
+                self.emit_op(OpCode::NOT);
+            }
+            BinaryOp::Less => self.emit_op(OpCode::LESS),
+            BinaryOp::LessEqual => {
+                self.emit_op(OpCode::GREATER);
+                self.emit_op(OpCode::NOT)
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn call(&mut self, callee: &Expr, args: &[Expr]) -> COMPError<()> {
+        // the callee goes below its arguments
+        self.expression(callee)?;
+        for arg in args {
+            self.expression(arg)?;
+        }
+        // the parser refuses more than 255 arguments
+        self.emit_op(OpCode::CALL(args.len() as u8));
+        Ok(())
+    }
+
+    pub(super) fn invoke(&mut self, target: &Expr, name: &Ident, args: &[Expr]) -> COMPError<()> {
+        self.expression(target)?;
+        for arg in args {
+            self.expression(arg)?;
+        }
+        let name_idx = self.make_const(Value::String(name.name.clone()))?;
+        self.emit_op(OpCode::INVOKE(name_idx, args.len() as u8));
+        Ok(())
+    }
+
+    pub(super) fn logical(&mut self, op: LogicalOp, lhs: &Expr, rhs: &Expr) -> COMPError<()> {
+        self.expression(lhs)?;
+        match op {
+            LogicalOp::And => self.and_(rhs),
+            LogicalOp::Or => self.or_(rhs),
+        }
+    }
+
+    fn and_(&mut self, rhs: &Expr) -> COMPError<()> {
+        let after_fst_expr_ip = self.emit_jump(OpCode::JUMP_IF_FALSE(0xFFFF));
+        self.expression(rhs)?;
+        self.emit_op(OpCode::AND);
+        let after_snd_expr_ip = self.here()?;
+        self.patch_jumps(
+            OpCode::JUMP_IF_FALSE(after_snd_expr_ip),
+            &[after_fst_expr_ip],
+        );
+        Ok(())
+    }
+
+    fn or_(&mut self, rhs: &Expr) -> COMPError<()> {
+        let after_fst_expr_ip = self.emit_jump(OpCode::JUMP_IF_FALSE(0xFFFF));
+        self.expression(rhs)?;
+        self.emit_op(OpCode::AND);
+        let after_snd_expr_ip = self.here()?;
+        self.patch_jumps(
+            OpCode::JUMP_IF_FALSE(after_snd_expr_ip),
+            &[after_fst_expr_ip],
+        );
+        Ok(())
+    }
+}
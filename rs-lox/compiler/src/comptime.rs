@@ -1,4 +1,4 @@
-use std::{borrow::Borrow, iter};
+use std::borrow::Borrow;
 
 type CountTy = i16;
 
@@ -49,14 +49,17 @@ impl Compiler {
         self.depth > 0
     }
 
-    pub fn add_local(&mut self, ident_: String) {
+    /// Returns the stack slot of the new local
+    pub fn add_local(&mut self, ident_: String) -> u8 {
         let local = Local {
             name: ident_,
             depth: self.depth,
         };
 
+        let slot = self.count as u8;
         self.locals[self.count as usize] = local;
         self.count += 1;
+        slot
     }
 
     /// See if variable with same name exeists in current scope.
@@ -72,8 +75,6 @@ impl Compiler {
             .fold(false, |acc, (_, l)| l.name == name || acc);
         // for now i want to pattern match this
         if res {
-            dbg!(name);
-            dbg!(self.locals[..(self.count as usize)].to_vec());
             None
         } else {
             Some(())
@@ -115,7 +116,7 @@ impl Compiler {
     }
 }
 
-impl<'a> std::fmt::Display for Compiler {
+impl std::fmt::Display for Compiler {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<Locals: {} Cur Depth: {}>", self.count, self.depth)
     }
@@ -0,0 +1,380 @@
+use lang::{CompileError, Precedence, Scanner, TokenType};
+
+use super::{Cst, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, Trivia};
+use crate::COMPError;
+
+/// Build a lossless tree out of the source. Only fails if we can't scan the source at all (non ASCII),
+/// syntax errors end up in [`Cst::errors`] and the offending tokens are wrapped in error nodes.
+pub fn parse(source: &str) -> COMPError<Cst> {
+    let scanner = Scanner::lossless(source)?;
+    let mut builder = Builder {
+        scanner,
+        cur: SyntaxToken {
+            ty: TokenType::Error,
+            text: String::new(),
+            leading: vec![],
+            offset: 0,
+            line: 1,
+        },
+        errors: vec![],
+    };
+    builder.cur = builder.scan_next();
+    let root = builder.program();
+    Ok(Cst {
+        root,
+        errors: builder.errors,
+    })
+}
+
+struct Builder<'a> {
+    scanner: Scanner<'a>,
+    /// next significant token, already holding its leading trivia
+    cur: SyntaxToken,
+    errors: Vec<CompileError>,
+}
+
+impl<'a> Builder<'a> {
+    fn program(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Program);
+        while self.cur.ty != TokenType::EoF {
+            let before = self.cur.offset;
+            let decl = self.declaration();
+            node.children.push(SyntaxElement::Node(decl));
+            if self.cur.offset == before && self.cur.ty != TokenType::EoF {
+                // nothing got consumed, skip the token so we always make progress
+                let mut err = SyntaxNode::new(SyntaxKind::Error);
+                self.bump_into(&mut err);
+                node.children.push(SyntaxElement::Node(err));
+            }
+        }
+        self.bump_into(&mut node);
+        node
+    }
+
+    fn declaration(&mut self) -> SyntaxNode {
+        match self.cur.ty {
+            TokenType::Var => self.var_declaration(),
+            TokenType::Test => {
+                let mut node = SyntaxNode::new(SyntaxKind::TestDecl);
+                self.bump_into(&mut node);
+                self.expect(&mut node, TokenType::String);
+                if self.cur.ty == TokenType::LeftBrace {
+                    self.statement_into(&mut node);
+                } else {
+                    self.error("Expected a block after the test name");
+                }
+                node
+            }
+            _ => self.statement(),
+        }
+    }
+
+    fn statement(&mut self) -> SyntaxNode {
+        match self.cur.ty {
+            TokenType::Print => {
+                let mut node = SyntaxNode::new(SyntaxKind::PrintStmt);
+                self.bump_into(&mut node);
+                self.expression_into(&mut node, Precedence::None);
+                self.expect(&mut node, TokenType::Semicolon);
+                node
+            }
+            TokenType::If => {
+                let mut node = SyntaxNode::new(SyntaxKind::IfStmt);
+                self.bump_into(&mut node);
+                self.condition_into(&mut node);
+                self.statement_into(&mut node);
+                if self.cur.ty == TokenType::Else {
+                    self.bump_into(&mut node);
+                    self.statement_into(&mut node);
+                }
+                node
+            }
+            TokenType::While => {
+                let mut node = SyntaxNode::new(SyntaxKind::WhileStmt);
+                self.bump_into(&mut node);
+                self.condition_into(&mut node);
+                self.statement_into(&mut node);
+                node
+            }
+            TokenType::Assert | TokenType::AssertEq => {
+                let mut node = SyntaxNode::new(SyntaxKind::AssertStmt);
+                let ty = self.cur.ty;
+                self.bump_into(&mut node);
+                self.expect(&mut node, TokenType::LeftParen);
+                self.expression_into(&mut node, Precedence::None);
+                if ty == TokenType::AssertEq {
+                    self.expect(&mut node, TokenType::Comma);
+                    self.expression_into(&mut node, Precedence::None);
+                }
+                self.expect(&mut node, TokenType::RightParen);
+                self.expect(&mut node, TokenType::Semicolon);
+                node
+            }
+            TokenType::For => self.for_(),
+            TokenType::LeftBrace => self.block(),
+            _ => {
+                let mut node = SyntaxNode::new(SyntaxKind::ExprStmt);
+                self.expression_into(&mut node, Precedence::None);
+                self.expect(&mut node, TokenType::Semicolon);
+                node
+            }
+        }
+    }
+
+    fn statement_into(&mut self, node: &mut SyntaxNode) {
+        let stmt = self.statement();
+        node.children.push(SyntaxElement::Node(stmt));
+    }
+
+    /// `( expression )` of if and while
+    fn condition_into(&mut self, node: &mut SyntaxNode) {
+        self.expect(node, TokenType::LeftParen);
+        self.expression_into(node, Precedence::None);
+        self.expect(node, TokenType::RightParen);
+    }
+
+    fn var_declaration(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::VarDecl);
+        self.bump_into(&mut node);
+        self.expect(&mut node, TokenType::Ident);
+        if self.cur.ty == TokenType::Equal {
+            self.bump_into(&mut node);
+            self.expression_into(&mut node, Precedence::None);
+        }
+        self.expect(&mut node, TokenType::Semicolon);
+        node
+    }
+
+    fn for_(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::ForStmt);
+        self.bump_into(&mut node);
+        self.expect(&mut node, TokenType::LeftParen);
+        match self.cur.ty {
+            TokenType::Semicolon => self.bump_into(&mut node),
+            TokenType::Var => {
+                let init = self.var_declaration();
+                node.children.push(SyntaxElement::Node(init));
+            }
+            _ => {
+                let mut init = SyntaxNode::new(SyntaxKind::ExprStmt);
+                self.expression_into(&mut init, Precedence::None);
+                self.expect(&mut init, TokenType::Semicolon);
+                node.children.push(SyntaxElement::Node(init));
+            }
+        }
+        if self.cur.ty != TokenType::Semicolon {
+            self.expression_into(&mut node, Precedence::None);
+        }
+        self.expect(&mut node, TokenType::Semicolon);
+        if self.cur.ty != TokenType::RightParen {
+            self.expression_into(&mut node, Precedence::None);
+        }
+        self.expect(&mut node, TokenType::RightParen);
+        self.statement_into(&mut node);
+        node
+    }
+
+    fn block(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Block);
+        self.bump_into(&mut node);
+        loop {
+            match self.cur.ty {
+                TokenType::RightBrace | TokenType::EoF => break,
+                _ => {
+                    let before = self.cur.offset;
+                    let decl = self.declaration();
+                    node.children.push(SyntaxElement::Node(decl));
+                    if self.cur.offset == before {
+                        let mut err = SyntaxNode::new(SyntaxKind::Error);
+                        self.bump_into(&mut err);
+                        node.children.push(SyntaxElement::Node(err));
+                    }
+                }
+            }
+        }
+        self.expect(&mut node, TokenType::RightBrace);
+        node
+    }
+
+    fn expression_into(&mut self, node: &mut SyntaxNode, min_prec: Precedence) {
+        let expr = self.expression(min_prec);
+        node.children.push(SyntaxElement::Node(expr));
+    }
+
+    fn expression(&mut self, min_prec: Precedence) -> SyntaxNode {
+        use TokenType::*;
+
+        let mut lhs = match self.cur.ty {
+            Number | String | True | False | Nil => {
+                let mut node = SyntaxNode::new(SyntaxKind::Literal);
+                self.bump_into(&mut node);
+                node
+            }
+            LeftParen => {
+                let mut node = SyntaxNode::new(SyntaxKind::Grouping);
+                self.bump_into(&mut node);
+                self.expression_into(&mut node, Precedence::None);
+                self.expect(&mut node, RightParen);
+                node
+            }
+            Minus | Bang => {
+                let mut node = SyntaxNode::new(SyntaxKind::Unary);
+                self.bump_into(&mut node);
+                self.expression_into(&mut node, Precedence::Unary);
+                node
+            }
+            Ident => {
+                let ident_ = self.bump();
+                if self.cur.ty == Equal {
+                    let mut node = SyntaxNode::new(SyntaxKind::Assign);
+                    node.children.push(SyntaxElement::Token(ident_));
+                    self.bump_into(&mut node);
+                    self.expression_into(&mut node, Precedence::None);
+                    node
+                } else {
+                    let mut node = SyntaxNode::new(SyntaxKind::Variable);
+                    node.children.push(SyntaxElement::Token(ident_));
+                    node
+                }
+            }
+            _ => {
+                self.error("Bad Expression");
+                // statement terminators and closers are left for the enclosing rule
+                let mut node = SyntaxNode::new(SyntaxKind::Error);
+                if !matches!(self.cur.ty, Semicolon | RightParen | RightBrace | EoF) {
+                    self.bump_into(&mut node);
+                }
+                return node;
+            }
+        };
+
+        loop {
+            let next_prec = Precedence::from(self.cur.ty);
+            if min_prec >= next_prec {
+                return lhs;
+            }
+            match self.cur.ty {
+                Minus | Plus | Slash | Star | EqualEqual | BangEqual | Greater | GreaterEqual
+                | LessEqual | Less | And | Or => {
+                    let mut node = SyntaxNode::new(SyntaxKind::Binary);
+                    node.children.push(SyntaxElement::Node(lhs));
+                    let op = self.cur.ty;
+                    self.bump_into(&mut node);
+                    self.expression_into(&mut node, op.into());
+                    lhs = node;
+                }
+                LeftParen => {
+                    let mut node = SyntaxNode::new(SyntaxKind::Call);
+                    node.children.push(SyntaxElement::Node(lhs));
+                    self.bump_into(&mut node);
+                    self.arguments_into(&mut node);
+                    lhs = node;
+                }
+                Dot => {
+                    let mut node = SyntaxNode::new(SyntaxKind::Invoke);
+                    node.children.push(SyntaxElement::Node(lhs));
+                    self.bump_into(&mut node);
+                    self.expect(&mut node, Ident);
+                    self.expect(&mut node, LeftParen);
+                    self.arguments_into(&mut node);
+                    lhs = node;
+                }
+                LeftBracket => {
+                    let mut node = SyntaxNode::new(SyntaxKind::Index);
+                    node.children.push(SyntaxElement::Node(lhs));
+                    self.bump_into(&mut node);
+                    self.expression_into(&mut node, Precedence::None);
+                    self.expect(&mut node, RightBracket);
+                    lhs = node;
+                }
+                _ => return lhs,
+            }
+        }
+    }
+
+    /// the arguments of a call after the opening paren, up to the closing one
+    fn arguments_into(&mut self, node: &mut SyntaxNode) {
+        if self.cur.ty != TokenType::RightParen {
+            self.expression_into(node, Precedence::None);
+            while self.cur.ty == TokenType::Comma {
+                self.bump_into(node);
+                self.expression_into(node, Precedence::None);
+            }
+        }
+        self.expect(node, TokenType::RightParen);
+    }
+
+    /// consume the expected token, or note the error and leave the current token for someone else
+    fn expect(&mut self, node: &mut SyntaxNode, ty: TokenType) {
+        if self.cur.ty == ty {
+            self.bump_into(node);
+        } else {
+            let err = CompileError::unexpected(
+                self.scanner.ascii_chars,
+                self.cur.ty,
+                ty,
+                self.cur.offset,
+                self.cur.offset + self.cur.text.len(),
+            );
+            self.errors.push(err);
+        }
+    }
+
+    fn error(&mut self, msg: &str) {
+        let err = CompileError::syntax(
+            self.scanner.ascii_chars,
+            msg,
+            self.cur.offset,
+            self.cur.offset + self.cur.text.len(),
+        );
+        self.errors.push(err);
+    }
+
+    fn bump_into(&mut self, node: &mut SyntaxNode) {
+        let tok = self.bump();
+        node.children.push(SyntaxElement::Token(tok));
+    }
+
+    fn bump(&mut self) -> SyntaxToken {
+        let next = self.scan_next();
+        std::mem::replace(&mut self.cur, next)
+    }
+
+    /// Scan the next significant token and gather the trivia on the way
+    fn scan_next(&mut self) -> SyntaxToken {
+        let mut leading = vec![];
+        let next = loop {
+            let scanned = self.scanner.scan_token();
+            let text = self.scanner.lexeme().to_string();
+            let offset = self.scanner.start_pos;
+            match scanned {
+                Ok(tok) => match tok.ty {
+                    TokenType::Whitespace => leading.push(Trivia::Whitespace(text)),
+                    TokenType::Newline => leading.push(Trivia::Newline),
+                    TokenType::Comment => leading.push(Trivia::Comment(text)),
+                    ty => {
+                        break SyntaxToken {
+                            ty,
+                            text,
+                            leading,
+                            offset,
+                            line: tok.line,
+                        }
+                    }
+                },
+                Err(e) => {
+                    // keep the bad chars around as an error token
+                    self.errors.push(e);
+                    break SyntaxToken {
+                        ty: TokenType::Error,
+                        text,
+                        leading,
+                        offset,
+                        line: self.scanner.line,
+                    };
+                }
+            }
+        };
+        next
+    }
+}
@@ -0,0 +1,203 @@
+//! Lossless concrete syntax tree.
+//! Every byte of the source ends up in a token, whitespace and comments are kept as leading trivia
+//! of the token that follows them. Printing the tree gives back the exact source.
+use std::fmt;
+
+use lang::{CompileError, TokenType};
+
+mod builder;
+
+pub use builder::parse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    VarDecl,
+    PrintStmt,
+    ExprStmt,
+    Block,
+    IfStmt,
+    WhileStmt,
+    ForStmt,
+    TestDecl,
+    AssertStmt,
+    Literal,
+    Variable,
+    Assign,
+    Grouping,
+    Unary,
+    Binary,
+    Call,
+    Index,
+    Invoke,
+    /// tokens we couldn't make sense of
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace(String),
+    Newline,
+    Comment(String),
+}
+
+impl Trivia {
+    pub fn text(&self) -> &str {
+        match self {
+            Trivia::Whitespace(s) | Trivia::Comment(s) => s,
+            Trivia::Newline => "\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub ty: TokenType,
+    /// raw source text, strings keep their quotes
+    pub text: String,
+    /// whitespace and comments found before this token
+    pub leading: Vec<Trivia>,
+    /// absolute char offset of the token text
+    pub offset: usize,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+/// Result of a lossless parse. The tree is always complete, errors are collected on the side
+pub struct Cst {
+    pub root: SyntaxNode,
+    pub errors: Vec<CompileError>,
+}
+
+impl SyntaxNode {
+    pub fn new(kind: SyntaxKind) -> Self {
+        Self {
+            kind,
+            children: vec![],
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|c| match c {
+            SyntaxElement::Node(n) => Some(n),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// All tokens in source order, including the ones of nested nodes
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut out = vec![];
+        self.collect_tokens(&mut out);
+        out
+    }
+
+    fn collect_tokens<'a>(&'a self, out: &mut Vec<&'a SyntaxToken>) {
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(n) => n.collect_tokens(out),
+                SyntaxElement::Token(t) => out.push(t),
+            }
+        }
+    }
+
+    pub fn first_token(&self) -> Option<&SyntaxToken> {
+        self.children.iter().find_map(|c| match c {
+            SyntaxElement::Node(n) => n.first_token(),
+            SyntaxElement::Token(t) => Some(t),
+        })
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for t in self.leading.iter() {
+            write!(f, "{}", t.text())?;
+        }
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(n) => write!(f, "{}", n)?,
+                SyntaxElement::Token(t) => write!(f, "{}", t)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn lox_files(dir: &Path, out: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                if path.file_name().is_some_and(|n| n != "target") {
+                    lox_files(&path, out);
+                }
+            } else if path.extension().is_some_and(|e| e == "lox") {
+                out.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_repo_files() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut files = vec![];
+        lox_files(&root, &mut files);
+        assert!(!files.is_empty());
+
+        for path in files {
+            let source = fs::read_to_string(&path).unwrap();
+            let cst = parse(&source).unwrap();
+            assert_eq!(cst.root.to_string(), source, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn round_trip_trivia_and_errors() {
+        let sources = [
+            "",
+            "// only a comment",
+            "  var a = 1; // trailing\n\n\t print a ;\n",
+            "print \"multi\nline\";\n// end",
+            "var = ; { print 1 + ; \"open",
+        ];
+        for source in sources {
+            let cst = parse(source).unwrap();
+            assert_eq!(cst.root.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn comments_are_leading_trivia() {
+        let cst = parse("// hi\nprint 1;").unwrap();
+        assert!(cst.errors.is_empty());
+        let print = cst.root.nodes().next().unwrap();
+        assert_eq!(print.kind, SyntaxKind::PrintStmt);
+        let first = print.first_token().unwrap();
+        assert_eq!(
+            first.leading,
+            vec![Trivia::Comment("// hi".to_string()), Trivia::Newline]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Canonical formatting of Lox source, works on the lossless tree so comments survive.
+//!
+//! The style: 4 space indent, one statement per line, single spaces around binary operators,
+//! opening braces on the same line, `} else` on the closing brace line, at most one blank line
+//! between statements and none at the start or end of a block.
+use lang::TokenType;
+
+use crate::cst::{self, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, Trivia};
+use crate::COMPError;
+
+const INDENT: &str = "    ";
+
+/// Format a whole source file. Code with syntax errors is refused, we don't guess what it means
+pub fn format(source: &str) -> COMPError<String> {
+    let mut tree = cst::parse(source)?;
+    if !tree.errors.is_empty() {
+        return Err(tree.errors.remove(0));
+    }
+    let mut fmt = Formatter::default();
+    fmt.program(&tree.root);
+    Ok(fmt.finish())
+}
+
+#[derive(Default)]
+struct Formatter {
+    buf: String,
+    indent: usize,
+    /// statement continues on a new line (after a comment in the middle of it)
+    continued: bool,
+    line_open: bool,
+    at_line_start: bool,
+    /// nothing written yet in the current block (or file)
+    first_in_block: bool,
+    /// offset of the token whose trivia was already handled on the statement level
+    handled_trivia: Option<usize>,
+    /// last written token, used to decide on spacing
+    prev: Option<TokenType>,
+    prev_unary: bool,
+}
+
+impl Formatter {
+    fn program(&mut self, root: &SyntaxNode) {
+        self.first_in_block = true;
+        for child in root.children.iter() {
+            match child {
+                SyntaxElement::Node(n) => self.stmt(n, true),
+                // the EoF token holds the comments at the end of the file
+                SyntaxElement::Token(t) => {
+                    self.leading(&t.leading);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if self.line_open {
+            self.buf.push('\n');
+        }
+        self.buf
+    }
+
+    /// Write a statement. `fresh` statements start on their own line,
+    /// otherwise they continue the current one (bodies of if, while and for)
+    fn stmt(&mut self, node: &SyntaxNode, fresh: bool) {
+        if fresh {
+            if let Some(first) = node.first_token() {
+                let newlines = self.leading(&first.leading);
+                if newlines > 1 && !self.first_in_block {
+                    self.blank();
+                }
+                self.handled_trivia = Some(first.offset);
+            }
+            self.continued = false;
+            self.new_line();
+            self.first_in_block = false;
+        }
+
+        match node.kind {
+            SyntaxKind::Block => self.block(node, fresh),
+            SyntaxKind::IfStmt
+            | SyntaxKind::WhileStmt
+            | SyntaxKind::ForStmt
+            | SyntaxKind::TestDecl => self.compound(node),
+            _ => self.inline(&node.children, None),
+        }
+    }
+
+    /// if, while, for and test: a header written inline, followed by bodies
+    fn compound(&mut self, node: &SyntaxNode) {
+        let mut header: Vec<&SyntaxElement> = vec![];
+        let mut after_block = false;
+        for child in node.children.iter() {
+            match child {
+                SyntaxElement::Node(n) if is_stmt(n.kind) && !header_part(node, n) => {
+                    self.inline_refs(&header);
+                    header.clear();
+                    self.body(n);
+                    after_block = n.kind == SyntaxKind::Block;
+                }
+                SyntaxElement::Token(t) if t.ty == TokenType::Else => {
+                    // a comment before `else` stays where it was, `else` goes on the next line
+                    let commented = has_comment(&t.leading);
+                    if commented {
+                        self.leading(&t.leading);
+                        self.handled_trivia = Some(t.offset);
+                    }
+                    if !after_block || commented {
+                        self.continued = false;
+                        self.new_line();
+                    }
+                    header.push(child);
+                }
+                _ => header.push(child),
+            }
+        }
+        self.inline_refs(&header);
+    }
+
+    fn body(&mut self, node: &SyntaxNode) {
+        self.stmt(node, false);
+    }
+
+    fn block(&mut self, node: &SyntaxNode, fresh: bool) {
+        let mut tokens = node.children.iter().filter_map(|c| match c {
+            SyntaxElement::Token(t) => Some(t),
+            SyntaxElement::Node(_) => None,
+        });
+        let (lbrace, rbrace) = (tokens.next(), tokens.next());
+        if let Some(lbrace) = lbrace {
+            if self.handled_trivia != Some(lbrace.offset) && has_comment(&lbrace.leading) {
+                // `while (c) // note` keeps its comment, the brace opens the next line
+                self.leading(&lbrace.leading);
+                self.handled_trivia = Some(lbrace.offset);
+                self.continued = false;
+                self.new_line();
+            }
+            self.token(lbrace, !fresh);
+        }
+
+        self.indent += 1;
+        self.first_in_block = true;
+        for n in node.nodes() {
+            self.stmt(n, true);
+        }
+        if let Some(rbrace) = rbrace {
+            self.leading(&rbrace.leading);
+        }
+        self.indent -= 1;
+
+        let empty = self.first_in_block;
+        self.first_in_block = false;
+        self.continued = false;
+        if !empty {
+            self.new_line();
+        }
+        self.word("}", false);
+        self.prev = Some(TokenType::RightBrace);
+        self.prev_unary = false;
+    }
+
+    /// Handle comments and blank lines found before a statement (or a closing brace).
+    /// Returns how many newlines came after the last comment
+    fn leading(&mut self, trivia: &[Trivia]) -> usize {
+        let mut newlines = 0;
+        for t in trivia {
+            match t {
+                Trivia::Whitespace(_) => {}
+                Trivia::Newline => newlines += 1,
+                Trivia::Comment(c) => {
+                    if newlines == 0 && self.line_open {
+                        // comment at the end of the previous line
+                        self.word(c, true);
+                    } else {
+                        if newlines > 1 && !self.first_in_block {
+                            self.blank();
+                        }
+                        self.continued = false;
+                        self.new_line();
+                        self.word(c, false);
+                        self.first_in_block = false;
+                    }
+                    newlines = 0;
+                }
+            }
+        }
+        newlines
+    }
+
+    /// Write the elements on the current line, `kind` is the expression node they belong to
+    fn inline(&mut self, elems: &[SyntaxElement], kind: Option<SyntaxKind>) {
+        let unary = kind == Some(SyntaxKind::Unary);
+        // the paren of a call and the bracket of an index stick to what comes before them,
+        // so do the dot, the name and the paren of a method call
+        let tight = match kind {
+            Some(SyntaxKind::Call | SyntaxKind::Index) => 1..2,
+            Some(SyntaxKind::Invoke) => 1..4,
+            _ => 0..0,
+        };
+        for (idx, el) in elems.iter().enumerate() {
+            match el {
+                SyntaxElement::Token(t) => {
+                    let space = self.space_before(t.ty) && !tight.contains(&idx);
+                    self.token(t, space);
+                    self.prev_unary = unary && idx == 0;
+                }
+                SyntaxElement::Node(n) if is_stmt(n.kind) && n.kind != SyntaxKind::ExprStmt => {
+                    self.stmt(n, false)
+                }
+                SyntaxElement::Node(n) => self.inline(&n.children, Some(n.kind)),
+            }
+        }
+    }
+
+    fn inline_refs(&mut self, elems: &[&SyntaxElement]) {
+        for el in elems {
+            self.inline(std::slice::from_ref(*el), None);
+        }
+    }
+
+    fn space_before(&self, next: TokenType) -> bool {
+        use TokenType::*;
+        match self.prev {
+            None => false,
+            Some(LeftParen | LeftBracket) => false,
+            Some(Assert | AssertEq) if next == LeftParen => false,
+            Some(_) if self.prev_unary => false,
+            _ => !matches!(next, RightParen | RightBracket | Semicolon | Comma | Dot),
+        }
+    }
+
+    fn token(&mut self, tok: &SyntaxToken, space: bool) {
+        if self.handled_trivia != Some(tok.offset) {
+            let mut space = space;
+            for t in tok.leading.iter() {
+                if let Trivia::Comment(c) = t {
+                    // a line comment in the middle of a statement, the rest goes on the next line
+                    self.word(c, self.line_open);
+                    self.continued = true;
+                    self.new_line();
+                    space = false;
+                }
+            }
+            self.word(&tok.text, space);
+        } else {
+            self.word(&tok.text, false);
+        }
+        self.handled_trivia = None;
+        self.prev = Some(tok.ty);
+        self.prev_unary = false;
+    }
+
+    fn word(&mut self, text: &str, space: bool) {
+        if !self.line_open {
+            self.new_line();
+        }
+        if space && !self.at_line_start {
+            self.buf.push(' ');
+        }
+        self.buf.push_str(text);
+        self.at_line_start = false;
+    }
+
+    fn new_line(&mut self) {
+        if self.line_open {
+            self.buf.push('\n');
+        }
+        for _ in 0..(self.indent + self.continued as usize) {
+            self.buf.push_str(INDENT);
+        }
+        self.line_open = true;
+        self.at_line_start = true;
+        self.prev = None;
+    }
+
+    fn blank(&mut self) {
+        if self.line_open {
+            self.buf.push('\n');
+            self.line_open = false;
+        }
+        self.buf.push('\n');
+    }
+}
+
+fn has_comment(trivia: &[Trivia]) -> bool {
+    trivia.iter().any(|t| matches!(t, Trivia::Comment(_)))
+}
+
+fn is_stmt(kind: SyntaxKind) -> bool {
+    use SyntaxKind::*;
+    matches!(
+        kind,
+        VarDecl
+            | PrintStmt
+            | ExprStmt
+            | Block
+            | IfStmt
+            | WhileStmt
+            | ForStmt
+            | TestDecl
+            | AssertStmt
+    )
+}
+
+/// The initializer of a for loop is a statement node, but it belongs to the loop header
+fn header_part(parent: &SyntaxNode, node: &SyntaxNode) -> bool {
+    parent.kind == SyntaxKind::ForStmt
+        && matches!(node.kind, SyntaxKind::VarDecl | SyntaxKind::ExprStmt)
+        && !std::ptr::eq(last_node(parent), node)
+}
+
+fn last_node(node: &SyntaxNode) -> &SyntaxNode {
+    node.nodes().last().unwrap_or(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_layout() {
+        let source = "var a=1;{print a+2*-a;   if(a>0){print \"pos\";}else print(a);}\n\n\n\nwhile(a<3)a=a+1;";
+        let expected = "var a = 1;\n{\n    print a + 2 * -a;\n    if (a > 0) {\n        print \"pos\";\n    } else print (a);\n}\n\nwhile (a < 3) a = a + 1;\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "// head\nvar a = 1; // tail\n{\n\n  // inside\n  print a;\n  // last\n}\nfor (var i=0;i<2;i=i+1) {} // end\n";
+        let expected = "// head\nvar a = 1; // tail\n{\n    // inside\n    print a;\n    // last\n}\nfor (var i = 0; i < 2; i = i + 1) {} // end\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn trailing_comments_stay_on_their_line() {
+        let source = "if (a) { print 1; } // yes\nelse { print 2; }\nif (a) print 1; // one\nelse print 2;\nwhile (c) // note\n{\n  print 1;\n}\n";
+        let expected = "if (a) {\n    print 1;\n} // yes\nelse {\n    print 2;\n}\nif (a) print 1; // one\nelse print 2;\nwhile (c) // note\n{\n    print 1;\n}\n";
+        let once = format(source).unwrap();
+        assert_eq!(once, expected);
+        assert_eq!(format(&once).unwrap(), once);
+
+        // the same inside a block
+        let source =
+            "{\nif (a) print 1; // one\nelse print 2;\nwhile (c) // note\n{ print 1; }\n}\n";
+        let expected = "{\n    if (a) print 1; // one\n    else print 2;\n    while (c) // note\n    {\n        print 1;\n    }\n}\n";
+        let once = format(source).unwrap();
+        assert_eq!(once, expected);
+        assert_eq!(format(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn idempotent() {
+        let sources = [
+            "var a = 1 + // why\n 2;",
+            "if (true) // then\n print 1; else { }",
+            "{ { print 1; } }\n\n\n// bye",
+            include_str!("../../all_tests.lox"),
+            include_str!("../../test.lox"),
+        ];
+        for source in sources {
+            let once = format(source).unwrap();
+            assert_eq!(format(&once).unwrap(), once, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn tests_and_asserts() {
+        let source = "test   \"it\"{assert ( 1<2 ) ;assert_eq(1 ,1);}";
+        let expected = "test \"it\" {\n    assert(1 < 2);\n    assert_eq(1, 1);\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn calls_and_indexes() {
+        let source = "#!/usr/bin/env rs-lox\nprint getenv ( \"HOME\" ,args [ 0 ] )(1)[2] . trim ( ) .split(\",\");";
+        let expected =
+            "#!/usr/bin/env rs-lox\nprint getenv(\"HOME\", args[0])(1)[2].trim().split(\",\");\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn refuses_broken_code() {
+        assert!(format("print ;").is_err());
+    }
+}
@@ -1,15 +1,143 @@
+pub mod analysis;
+pub mod ast;
+mod codegen;
 mod comptime;
+pub mod cst;
+pub mod format;
 mod parser;
 
+pub use codegen::CodeGen;
 pub use comptime::{Compiler, Local};
 pub use parser::COMPError;
 pub use parser::Parser;
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use ast::{BinaryOp, ExprKind, StmtKind};
+    use lang::{CompileError, InstructAddr, Scanner};
+    use values::Chunk;
+
+    fn parse(source: &str) -> ast::Program {
+        let mut scanner = Scanner::from_str(source).unwrap();
+        Parser::init(&mut scanner).parse().unwrap()
+    }
+
+    fn ops(source: &str) -> String {
+        let program = parse(source);
+        let mut chunk = Chunk::new();
+        CodeGen::init(&mut chunk).generate(&program).unwrap();
+        chunk
+            .instructions()
+            .map(|(_, op)| format!("{:?}", op))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn parse_precedence_and_spans() {
+        let program = parse("print 1 + 2 * 3;");
+        let print = &program.decls[0];
+        assert_eq!((print.span.start, print.span.end), (0, 16));
+        let StmtKind::Print(expr) = &print.kind else {
+            panic!("expected print, got {:?}", print.kind)
+        };
+        let ExprKind::Binary { op, rhs, .. } = &expr.kind else {
+            panic!("expected binary, got {:?}", expr.kind)
+        };
+        assert_eq!(*op, BinaryOp::Add);
+        assert!(matches!(
+            rhs.kind,
+            ExprKind::Binary {
+                op: BinaryOp::Mul,
+                ..
+            }
+        ));
+        assert_eq!((rhs.span.start, rhs.span.end), (10, 15));
+    }
+
+    #[test]
+    fn codegen_locals_and_jumps() {
+        assert_eq!(
+            ops("{ var a = 1; if (a > 0) print a; }"),
+            "CONSTANT(0) GET_LOCAL(0) CONSTANT(1) GREATER JUMP_IF_FALSE(17) POP GET_LOCAL(0) PRINT JUMP(18) POP POP RETURN"
+        );
+        assert_eq!(
+            ops("var b = 2; b = b - 1;"),
+            "CONSTANT(0) DEFINE_GLOBAL(1) GET_GLOBAL(2) CONSTANT(3) SUB SET_GLOBAL(4) POP RETURN"
+        );
+    }
+
+    #[test]
+    fn codegen_calls_and_indexes() {
+        // the shebang line is skipped like a comment
+        assert_eq!(
+            ops("#!/usr/bin/env rs-lox\nprint f(1, a[0]);"),
+            "GET_GLOBAL(0) CONSTANT(1) GET_GLOBAL(2) CONSTANT(3) INDEX CALL(2) PRINT RETURN"
+        );
+        assert_eq!(ops("f()();"), "GET_GLOBAL(0) CALL(0) CALL(0) POP RETURN");
+        assert_eq!(
+            ops("s.split(\",\")[0].trim();"),
+            "GET_GLOBAL(0) CONSTANT(1) INVOKE(2, 1) CONSTANT(3) INDEX INVOKE(4, 0) POP RETURN"
+        );
+    }
+
+    #[test]
+    fn codegen_keeps_the_stack_balanced() {
+        let source = "var s = 0;
+for (var i = 0; i < 3; i = i + 1) {
+    if (i > 1) s = s + 1; else { var t = i; }
+    while (s > 5 and i < 2) s = s - 1;
+}";
+        let mut chunk = Chunk::new();
+        CodeGen::init(&mut chunk).generate(&parse(source)).unwrap();
+        assert_eq!(chunk.verify(), Ok(()));
+    }
+
+    #[test]
+    fn jumps_past_the_address_range() {
+        let filler = "true;\n".repeat(40_000);
+        let compile = |source: &str| {
+            let mut chunk = Chunk::new();
+            CodeGen::init(&mut chunk)
+                .generate(&parse(source))
+                .map(|_| chunk)
+        };
+        // more code than a jump can address is fine, as long as no jump has to go there
+        let chunk = compile(&format!("if (true) print 1;\n{}", filler)).unwrap();
+        assert!(chunk.count() > InstructAddr::MAX as usize);
+        assert_eq!(chunk.verify(), Ok(()));
+
+        let err = compile(&format!("if (false) {{\n{}}}\nprint \"after\";", filler)).unwrap_err();
+        assert!(matches!(err, CompileError::JumpTooLarge), "{:?}", err);
+        let err = compile(&format!("{}while (false) {{}}", filler)).unwrap_err();
+        assert!(matches!(err, CompileError::JumpTooLarge), "{:?}", err);
+    }
+
+    #[test]
+    fn source_map_points_at_expressions() {
+        let source = "var a = 1;\nprint (a +\n  2) * -\"x\";";
+        let mut chunk = Chunk::new();
+        CodeGen::init(&mut chunk).generate(&parse(source)).unwrap();
+        let pos_of = |wanted: &str| {
+            let (ip, _) = chunk
+                .instructions()
+                .find(|(_, op)| format!("{:?}", op) == wanted)
+                .unwrap();
+            let pos = chunk.get_pos(ip).unwrap();
+            (pos.line, pos.col, pos.len)
+        };
+        // operators cover their operands, the line is where the expression starts
+        assert_eq!(pos_of("ADD"), (2, 8, 7));
+        assert_eq!(pos_of("MUL"), (2, 7, 16));
+        assert_eq!(pos_of("NEGATE"), (3, 8, 4));
+        assert_eq!(pos_of("PRINT"), (2, 1, 23));
+        assert_eq!(chunk.get_line_num(0), 1);
+    }
 }
@@ -1,101 +1,129 @@
-use crate::Compiler;
-
 use lang::CompileError;
 pub type COMPError<T> = Result<T, CompileError>;
 
-use values::Value;
+use crate::ast::{Expr, ExprKind, Ident, Program, Span, Stmt, StmtKind};
 
-use lang::{ConstIdx, InstructAddr, OpCode};
 use lang::{Precedence, Scanner, Token, TokenType};
-use values::Chunk;
 
 mod conditionals;
 mod ops;
 
+/// Builds an AST out of the scanner tokens. Bytecode is emitted later by the [`crate::CodeGen`]
 pub struct Parser<'a> {
     cur: Token,
     prev: Token,
     had_error: bool,
     panic_mode: bool,
+    /// first thing the scanner choked on, it's the root cause of whatever error follows
+    scan_error: Option<CompileError>,
     scanner: &'a mut Scanner<'a>,
-    chunk: &'a mut Chunk,
-    compiler: Compiler,
 }
 
 impl<'a> Parser<'a> {
-    // pub fn init(scanner: &'a mut Scanner<'a>, chunk: &'a mut Chunk, heap:RefMut<'a, Heap>) -> Self {
-
-    fn declaration(&mut self) -> COMPError<()> {
+    fn declaration(&mut self) -> COMPError<Stmt> {
         match self.cur.ty {
-            TokenType::Var => self.var_declaration()?,
-            _ => self.statement()?,
+            TokenType::Var => self.var_declaration(),
+            _ => self.statement(),
         }
-        Ok(())
     }
 
-    fn statement(&mut self) -> COMPError<()> {
-        match self.cur.ty {
-            TokenType::Print => self.print()?,
-            TokenType::If => self.if_else()?,
-            TokenType::While => self.while_()?,
-            TokenType::For => self.for_()?,
-            TokenType::LeftBrace => self.scope()?,
-            _ => self.expression_statement()?,
+    /// `test "name" { .. }`, only allowed at the top level
+    fn test_declaration(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
+        self.move_to_next_token();
+        self.cur_must_be(TokenType::String)?;
+        let name = self.scanner.token_text(self.prev)?;
+        if self.cur.ty != TokenType::LeftBrace {
+            return self.syntax_err("Expected a block after the test name");
         }
-        Ok(())
+        let body = self.block()?;
+        Ok(self.stmt(
+            StmtKind::Test {
+                name,
+                body: Box::new(body),
+            },
+            start,
+        ))
     }
 
-    fn expression_statement(&mut self) -> COMPError<()> {
-        self.expression(Precedence::None)?;
+    /// `assert(cond);` and `assert_eq(lhs, rhs);`
+    fn assert(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
+        let ty = self.cur.ty;
+        self.move_to_next_token();
+        self.cur_must_be(TokenType::LeftParen)?;
+        let first = self.expression(Precedence::None)?;
+        let kind = if ty == TokenType::AssertEq {
+            self.cur_must_be(TokenType::Comma)?;
+            let second = self.expression(Precedence::None)?;
+            StmtKind::AssertEq(first, second)
+        } else {
+            StmtKind::Assert(first)
+        };
+        self.cur_must_be(TokenType::RightParen)?;
         self.cur_must_be(TokenType::Semicolon)?;
-        self.emit_op(OpCode::POP);
-        Ok(())
+        Ok(self.stmt(kind, start))
     }
 
-    fn scope(&mut self) -> COMPError<()> {
-        self.compiler.begin_scope();
-        self.block()?;
-
-        self.clean_locals();
-        self.compiler.end_scope();
+    fn statement(&mut self) -> COMPError<Stmt> {
+        match self.cur.ty {
+            TokenType::Print => self.print(),
+            TokenType::If => self.if_else(),
+            TokenType::While => self.while_(),
+            TokenType::For => self.for_(),
+            TokenType::LeftBrace => self.block(),
+            TokenType::Assert | TokenType::AssertEq => self.assert(),
+            TokenType::Test => self.syntax_err("Tests can only be declared at the top level"),
+            _ => self.expression_statement(),
+        }
+    }
 
-        Ok(())
+    fn expression_statement(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
+        let expr = self.expression(Precedence::None)?;
+        self.cur_must_be(TokenType::Semicolon)?;
+        Ok(self.stmt(StmtKind::Expression(expr), start))
     }
 
-    fn block(&mut self) -> COMPError<()> {
+    fn block(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
         self.move_to_next_token();
+        let mut stmts = vec![];
         loop {
             match self.cur.ty {
                 TokenType::RightBrace => break,
                 TokenType::EoF => self.syntax_err("EoF without block close")?,
                 _ => {
-                    self.declaration()?;
+                    stmts.push(self.declaration()?);
                 }
             }
         }
         self.cur_must_be(TokenType::RightBrace)?;
-        Ok(())
+        Ok(self.stmt(StmtKind::Block(stmts), start))
     }
 
-    fn expression(&mut self, min_prec: Precedence) -> COMPError<()> {
+    fn expression(&mut self, min_prec: Precedence) -> COMPError<Expr> {
         use TokenType::*;
 
         // do the prefix op first
         // TODO: remove this and work on self.cur
         self.move_to_next_token();
-        match self.prev.ty {
+        let mut expr = match self.prev.ty {
             Number => self.number()?,
             String => self.string()?,
             LeftParen => {
-                self.expression(Precedence::None)?;
+                let start = tok_span(self.prev);
+                let inner = self.expression(Precedence::None)?;
                 self.cur_must_be(RightParen)?;
+                self.expr(ExprKind::Grouping(Box::new(inner)), start)
             }
             True | False | Nil => self.literal()?,
             Minus | Bang => self.unary()?,
 
             Ident => self.identifier()?,
-            _ => self.syntax_err("Bad Expression")?,
-        }
+            // we already moved past the offending token
+            _ => return self.syntax_err_at(self.prev, "Bad Expression"),
+        };
 
         // now do the infix and the res of those
         // if there is no infix operator here, we are done since the expression was handled
@@ -108,87 +136,76 @@ impl<'a> Parser<'a> {
             let next_prec = Precedence::from(self.cur.ty);
             if min_prec >= next_prec {
                 // parse only stuff that has higher precedence than what we need
-                return Ok(());
+                return Ok(expr);
             }
 
             match self.cur.ty {
                 Minus | Plus | Slash | Star | EqualEqual | BangEqual | Greater | GreaterEqual
-                | LessEqual | Less | And | Or => self.binary()?,
+                | LessEqual | Less | And | Or => expr = self.binary(expr)?,
+                LeftParen => expr = self.call(expr)?,
+                LeftBracket => expr = self.index(expr)?,
+                Dot => expr = self.invoke(expr)?,
                 _ => break,
             }
         }
-        Ok(())
+        Ok(expr)
     }
 
-    fn var_declaration(&mut self) -> COMPError<()> {
-        let ident_ = self.get_ident()?;
-
-        // let var_name_const_idx = self.global_variable()?;
+    fn var_declaration(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
+        let name = self.get_ident()?;
 
-        if TokenType::Equal == self.cur.ty {
+        let init = if TokenType::Equal == self.cur.ty {
             self.move_to_next_token();
-            // if we have an expresion that initializes the var, calculate it and put on stack
-            self.expression(Precedence::None)?;
+            // if we have an expresion that initializes the var, keep it. no initializer means NIL
+            Some(self.expression(Precedence::None)?)
         } else {
-            // do nothing, we just made room on out var table for this one and push NIL inside
-            self.emit_op(OpCode::NIL);
-        }
+            None
+        };
 
-        if self.compiler.local_scope() {
-            self.compiler
-                .local_exists(&ident_)
-                .expect("TODO: proper handling of double def of local variable ");
-            self.compiler.add_local(ident_);
-            // at this point the variable is already on the stack and is going to be used in the scope
-            // it was deined in (or deeper scope)
-        } else {
-            let const_idx = self.chunk.add_const(Value::String(ident_.to_string())) as ConstIdx;
-            self.emit_op(OpCode::DEFINE_GLOBAL(const_idx));
-        }
         self.cur_must_be(TokenType::Semicolon)?;
-        Ok(())
+        Ok(self.stmt(StmtKind::Var { name, init }, start))
     }
 
-    fn identifier(&mut self) -> COMPError<()> {
-        let ident_ = self.scanner.token_text(self.prev)?;
-        let is_local = self.compiler.find_local(&ident_);
+    fn identifier(&mut self) -> COMPError<Expr> {
+        let name = Ident {
+            name: self.scanner.token_text(self.prev)?,
+            span: tok_span(self.prev),
+        };
+        let start = name.span;
 
         if let TokenType::Equal = self.cur.ty {
             self.move_to_next_token();
-            self.expression(Precedence::None)?;
-            match is_local {
-                Some(slot) => self.emit_op(OpCode::SET_LOCAL(slot)),
-                None => {
-                    let ident_idx = self.chunk.add_const(Value::String(ident_)) as u8;
-                    self.emit_op(OpCode::SET_GLOBAL(ident_idx))
-                }
-            }
+            let value = self.expression(Precedence::None)?;
+            Ok(self.expr(
+                ExprKind::Assign {
+                    name,
+                    value: Box::new(value),
+                },
+                start,
+            ))
         } else {
-            match is_local {
-                Some(slot) => self.emit_op(OpCode::GET_LOCAL(slot)),
-                None => {
-                    let ident_idx = self.chunk.add_const(Value::String(ident_)) as u8;
-                    self.emit_op(OpCode::GET_GLOBAL(ident_idx))
-                }
-            }
+            Ok(self.expr(ExprKind::Variable(name), start))
         }
-        Ok(())
     }
 
-    fn literal(&mut self) -> COMPError<()> {
-        match self.prev.ty {
-            TokenType::True => self.emit_op(OpCode::TRUE),
-            TokenType::False => self.emit_op(OpCode::FALSE),
-            TokenType::Nil => self.emit_op(OpCode::NIL),
-            _ => todo!(),
-        }
-        Ok(())
+    fn literal(&mut self) -> COMPError<Expr> {
+        let kind = match self.prev.ty {
+            TokenType::True => ExprKind::Bool(true),
+            TokenType::False => ExprKind::Bool(false),
+            TokenType::Nil => ExprKind::Nil,
+            _ => return self.syntax_err_at(self.prev, "Expected a literal"),
+        };
+        Ok(self.expr(kind, tok_span(self.prev)))
     }
 
-    fn get_ident(&mut self) -> COMPError<String> {
+    fn get_ident(&mut self) -> COMPError<Ident> {
         self.move_to_next_token();
         self.cur_must_be(TokenType::Ident)?;
-        Ok(self.scanner.token_txt_str(self.prev)?.to_string())
+        Ok(Ident {
+            name: self.scanner.token_txt_str(self.prev)?.to_string(),
+            span: tok_span(self.prev),
+        })
     }
 
     fn _dbg(&self, tok: Token) {
@@ -201,36 +218,32 @@ impl<'a> Parser<'a> {
         println!("{}", a);
     }
 
-    fn print(&mut self) -> COMPError<()> {
+    fn print(&mut self) -> COMPError<Stmt> {
+        let start = self.cur_start();
         self.move_to_next_token();
-        self.expression(Precedence::None)?;
+        let expr = self.expression(Precedence::None)?;
         self.cur_must_be(TokenType::Semicolon)?;
-        self.emit_op(OpCode::PRINT);
-        Ok(())
+        Ok(self.stmt(StmtKind::Print(expr), start))
     }
 
-    fn string(&mut self) -> COMPError<()> {
+    fn string(&mut self) -> COMPError<Expr> {
         let tok_txt = self
             .scanner
             .token_text(self.prev)
             .map_err(|_| CompileError::NonASCIIChar)?;
 
-        let const_idx = self.chunk.add_const(Value::String(tok_txt));
-        self.emit_op(OpCode::CONSTANT(const_idx as u8));
-        Ok(())
+        let span = tok_span(self.prev);
+        Ok(Expr {
+            kind: ExprKind::Str(tok_txt),
+            span,
+        })
     }
 
-    fn number(&mut self) -> COMPError<()> {
+    fn number(&mut self) -> COMPError<Expr> {
         // for now this thing is f32 only
         let tok_txt = self.scanner.token_text(self.prev)?;
         let num: f32 = tok_txt.parse().map_err(|_| CompileError::NonASCIIChar)?;
-
-        let const_idx = self.chunk.add_const(num.into());
-        if const_idx > (u8::MAX - 1) as usize {
-            return Err(CompileError::ToManyConstants);
-        }
-        self.emit_op(OpCode::CONSTANT(const_idx as u8));
-        Ok(())
+        Ok(self.expr(ExprKind::Number(num), tok_span(self.prev)))
     }
 
     fn move_to_next_token(&mut self) {
@@ -240,7 +253,9 @@ impl<'a> Parser<'a> {
                 self.cur = tok;
             }
             Err(e) => {
-                dbg!(e);
+                if self.scan_error.is_none() {
+                    self.scan_error = Some(e);
+                }
 
                 self.had_error = true;
                 self.panic_mode = true;
@@ -263,51 +278,96 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn emit_op(&mut self, op: OpCode) {
-        self.chunk.add_op(op, self.scanner.line as usize);
+    /// Span of the token we are about to consume, nodes start from there
+    fn cur_start(&self) -> Span {
+        tok_span(self.cur)
+    }
+
+    /// Node span runs from `start` to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(tok_span(self.prev))
+    }
+
+    fn stmt(&self, kind: StmtKind, start: Span) -> Stmt {
+        Stmt {
+            kind,
+            span: self.span_from(start),
+        }
     }
 
-    pub fn init(scanner: &'a mut Scanner<'a>, chunk: &'a mut Chunk) -> Self {
-        let compiler = Compiler::init();
+    fn expr(&self, kind: ExprKind, start: Span) -> Expr {
+        Expr {
+            kind,
+            span: self.span_from(start),
+        }
+    }
 
-        let parser = Self {
+    pub fn init(scanner: &'a mut Scanner<'a>) -> Self {
+        Self {
             cur: Token::empty(0),
             prev: Token::empty(0),
             had_error: false,
             panic_mode: false,
+            scan_error: None,
             scanner,
-            chunk,
-            compiler,
-        };
-        parser
+        }
     }
 
-    pub fn parse(&mut self) -> COMPError<()> {
-        // we got a scanner and a chunk, now it's time to start writing
-        self.move_to_next_token(); // get the first token for now ignore errors
-                                   //for now we only want to cath an expression
+    pub fn parse(&mut self) -> COMPError<Program> {
+        let res = self.parse_program();
+        match self.scan_error.take() {
+            Some(e) => Err(e),
+            None => res,
+        }
+    }
+
+    /// Parse a source holding a single expression
+    pub fn parse_expression(&mut self) -> COMPError<Expr> {
+        self.move_to_next_token();
+        let res = self
+            .expression(Precedence::None)
+            .and_then(|expr| self.cur_must_be(TokenType::EoF).map(|_| expr));
+        match self.scan_error.take() {
+            Some(e) => Err(e),
+            None => res,
+        }
+    }
+
+    fn parse_program(&mut self) -> COMPError<Program> {
+        // we got a scanner, now it's time to start building the tree
+        self.move_to_next_token(); // get the first token, scan errors are reported at the end
+        let mut program = Program::default();
         while self.cur.ty != TokenType::EoF {
-            self.declaration()?;
-            // self.expression(Precedence::Assignme"nt).unwrap();
+            let decl = match self.cur.ty {
+                TokenType::Test => self.test_declaration()?,
+                _ => self.declaration()?,
+            };
+            program.decls.push(decl);
         }
 
         self.cur_must_be(TokenType::EoF)?; // finished reading the whole scanner
-        self.emit_op(OpCode::RETURN);
-        Ok(())
+        Ok(program)
     }
 
-    fn syntax_err(&self, msg: &str) -> COMPError<()> {
+    fn syntax_err<T>(&self, msg: &str) -> COMPError<T> {
+        self.syntax_err_at(self.cur, msg)
+    }
+
+    fn syntax_err_at<T>(&self, tok: Token, msg: &str) -> COMPError<T> {
         Err(CompileError::syntax(
             self.scanner.ascii_chars,
             msg,
-            self.cur.start_pos,
-            self.cur.start_pos + self.cur.len,
+            tok.start_pos,
+            tok.start_pos + tok.len,
         ))
     }
+}
 
-    fn clean_locals(&mut self) {
-        while self.compiler.should_pop_local() {
-            self.emit_op(OpCode::POP);
-        }
-    }
+/// String tokens point at the string content, the span should include the quotes
+fn tok_span(tok: Token) -> Span {
+    let span = match tok.ty {
+        TokenType::String => Span::new(tok.start_pos - 1, tok.start_pos + tok.len + 1, tok.line),
+        _ => Span::new(tok.start_pos, tok.start_pos + tok.len, tok.line),
+    };
+    span.with_col(tok.col)
 }
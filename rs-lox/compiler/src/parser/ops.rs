@@ -1,94 +1,138 @@
 use super::*;
+
+use crate::ast::{BinaryOp, LogicalOp, UnaryOp};
+
 impl<'a> Parser<'a> {
-    pub(super) fn unary(&mut self) -> COMPError<()> {
+    pub(super) fn unary(&mut self) -> COMPError<Expr> {
         let op = self.prev.ty;
+        let start = tok_span(self.prev);
 
-        self.expression(Precedence::Unary)?;
+        let rhs = self.expression(Precedence::Unary)?;
 
-        match op {
-            TokenType::Minus => self.emit_op(OpCode::NEGATE),
-            TokenType::Bang => self.emit_op(OpCode::NOT),
-            _ => {}
+        let op = match op {
+            TokenType::Minus => UnaryOp::Neg,
+            TokenType::Bang => UnaryOp::Not,
+            _ => unreachable!("Not a unary op!!"),
         };
 
-        Ok(())
+        Ok(self.expr(
+            ExprKind::Unary {
+                op,
+                rhs: Box::new(rhs),
+            },
+            start,
+        ))
     }
 
-    pub(super) fn binary(&mut self) -> COMPError<()> {
-        // at this point we already have the left hand side expression result on the stack
-        // now we need to figure out what should we parse next. get that expression, push it on the stack
-        // and do the binary operation on both the values in the stack.
+    pub(super) fn binary(&mut self, lhs: Expr) -> COMPError<Expr> {
+        // at this point we already have the left hand side expression
+        // now we need to figure out what should we parse next. get that expression
+        // and combine both of them into a binary node.
 
         // the way bob does that in C is to have parse rules associated with every op token.
-        // i can gothe same way as bob did maybe i should star with that and see how i can improve from there
         // I think it's much easir to do that with some pattern matching or something inplace. no need for extra functions here
-        let op = self.cur.ty;
+        let op_tok = self.cur;
+        let op = op_tok.ty;
         self.move_to_next_token();
 
-        match op {
-            // do i need some kind of jump?
-            TokenType::And => self.and_(op.into())?,
-            TokenType::Or => self.or_(op.into())?,
-            _ => self.expression(op.into())?,
-        }
+        let rhs = self.expression(op.into())?;
+        let start = lhs.span;
+        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
 
-        // apply the binary op on both expressions
-        match op {
-            TokenType::Plus => self.emit_op(OpCode::ADD),
-            TokenType::Minus => self.emit_op(OpCode::SUB),
-            TokenType::Star => self.emit_op(OpCode::MUL),
-            TokenType::Slash => self.emit_op(OpCode::DIV),
-            TokenType::EqualEqual => self.emit_op(OpCode::EQUAL),
-            TokenType::BangEqual => {
-                self.emit_op(OpCode::EQUAL);
-                self.emit_op(OpCode::NOT);
-            }
-            TokenType::Greater => self.emit_op(OpCode::GREATER),
-            TokenType::GreaterEqual => {
-                self.emit_op(OpCode::LESS);
-                self.emit_op(OpCode::NOT);
-            }
-            TokenType::Less => self.emit_op(OpCode::LESS),
-            TokenType::LessEqual => {
-                self.emit_op(OpCode::GREATER);
-                self.emit_op(OpCode::NOT)
-            }
-            TokenType::And | TokenType::Or => {
-                // handled in previous match
-            }
+        let kind = match op {
+            TokenType::And => ExprKind::Logical {
+                op: LogicalOp::And,
+                lhs,
+                rhs,
+            },
+            TokenType::Or => ExprKind::Logical {
+                op: LogicalOp::Or,
+                lhs,
+                rhs,
+            },
             _ => {
-                dbg!(self.prev, self.cur);
-                todo!()
+                let op = match op {
+                    TokenType::Plus => BinaryOp::Add,
+                    TokenType::Minus => BinaryOp::Sub,
+                    TokenType::Star => BinaryOp::Mul,
+                    TokenType::Slash => BinaryOp::Div,
+                    TokenType::EqualEqual => BinaryOp::Equal,
+                    TokenType::BangEqual => BinaryOp::NotEqual,
+                    TokenType::Greater => BinaryOp::Greater,
+                    TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+                    TokenType::Less => BinaryOp::Less,
+                    TokenType::LessEqual => BinaryOp::LessEqual,
+                    _ => return self.syntax_err_at(op_tok, "Expected an operator"),
+                };
+                ExprKind::Binary { op, lhs, rhs }
             }
-        }
+        };
 
-        // let rulle = get_parse_rule(op);
-        Ok(())
+        Ok(self.expr(kind, start))
     }
 
-    fn and_(&mut self, prec: Precedence) -> COMPError<()> {
-        let after_fst_expr_ip = self.chunk.count();
-        self.emit_op(OpCode::JUMP_IF_FALSE(0xFFFF));
-        self.expression(prec)?;
-        self.emit_op(OpCode::AND);
-        let after_snd_expr_ip = self.chunk.count();
-        self.chunk.patch_op(
-            OpCode::JUMP_IF_FALSE(after_snd_expr_ip as InstructAddr),
-            after_fst_expr_ip,
-        );
-        Ok(())
+    /// `callee(args..)`, we are looking at the opening paren
+    pub(super) fn call(&mut self, callee: Expr) -> COMPError<Expr> {
+        self.move_to_next_token();
+        let args = self.arguments()?;
+        let start = callee.span;
+        Ok(self.expr(
+            ExprKind::Call {
+                callee: Box::new(callee),
+                args,
+            },
+            start,
+        ))
+    }
+
+    /// `target.name(args..)`, we are looking at the dot
+    pub(super) fn invoke(&mut self, target: Expr) -> COMPError<Expr> {
+        let name = self.get_ident()?;
+        // there are no fields, a name after a dot is always called
+        self.cur_must_be(TokenType::LeftParen)?;
+        let args = self.arguments()?;
+        let start = target.span;
+        Ok(self.expr(
+            ExprKind::Invoke {
+                target: Box::new(target),
+                name,
+                args,
+            },
+            start,
+        ))
+    }
+
+    /// The arguments of a call up to and including the closing paren
+    fn arguments(&mut self) -> COMPError<Vec<Expr>> {
+        let mut args = vec![];
+        if self.cur.ty != TokenType::RightParen {
+            loop {
+                if args.len() == u8::MAX as usize {
+                    return self.syntax_err("Can't have more than 255 arguments");
+                }
+                args.push(self.expression(Precedence::None)?);
+                if self.cur.ty != TokenType::Comma {
+                    break;
+                }
+                self.move_to_next_token();
+            }
+        }
+        self.cur_must_be(TokenType::RightParen)?;
+        Ok(args)
     }
 
-    fn or_(&mut self, prec: Precedence) -> COMPError<()> {
-        let after_fst_expr_ip = self.chunk.count();
-        self.emit_op(OpCode::JUMP_IF_FALSE(0xFFFF));
-        self.expression(prec)?;
-        self.emit_op(OpCode::AND);
-        let after_snd_expr_ip = self.chunk.count();
-        self.chunk.patch_op(
-            OpCode::JUMP_IF_FALSE(after_snd_expr_ip as InstructAddr),
-            after_fst_expr_ip,
-        );
-        Ok(())
+    /// `target[index]`, we are looking at the opening bracket
+    pub(super) fn index(&mut self, target: Expr) -> COMPError<Expr> {
+        self.move_to_next_token();
+        let index = self.expression(Precedence::None)?;
+        self.cur_must_be(TokenType::RightBracket)?;
+        let start = target.span;
+        Ok(self.expr(
+            ExprKind::Index {
+                target: Box::new(target),
+                index: Box::new(index),
+            },
+            start,
+        ))
     }
 }
@@ -10,3 +10,4 @@ pub use tokens::TokenType;
 pub use opcode::{ConstIdx, InstructAddr, OpCode};
 
 pub use scanner::{CompileError, Scanner};
+pub use utils::{cite_span, line_col, offset_of};
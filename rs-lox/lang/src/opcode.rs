@@ -1,6 +1,7 @@
 pub type ConstIdx = u8;
 pub type InstructAddr = u16;
 
+#[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum OpCode {
@@ -32,4 +33,245 @@ pub enum OpCode {
 
     JUMP_IF_FALSE(InstructAddr),
     JUMP(InstructAddr),
+
+    ASSERT,
+    ASSERT_EQ,
+
+    /// call the value below the arguments with that many arguments
+    CALL(u8),
+    /// `list[index]`
+    INDEX,
+    /// call the method named by the constant on the value below the arguments
+    INVOKE(ConstIdx, u8),
+}
+
+impl OpCode {
+    /// Name of the variant, without the operand
+    pub fn name(&self) -> &'static str {
+        use OpCode::*;
+        match self {
+            RETURN => "RETURN",
+            CONSTANT(_) => "CONSTANT",
+            NEGATE => "NEGATE",
+            NOT => "NOT",
+            NIL => "NIL",
+            TRUE => "TRUE",
+            FALSE => "FALSE",
+            EQUAL => "EQUAL",
+            LESS => "LESS",
+            GREATER => "GREATER",
+            ADD => "ADD",
+            SUB => "SUB",
+            MUL => "MUL",
+            DIV => "DIV",
+            AND => "AND",
+            OR => "OR",
+            PRINT => "PRINT",
+            POP => "POP",
+            DEFINE_GLOBAL(_) => "DEFINE_GLOBAL",
+            GET_GLOBAL(_) => "GET_GLOBAL",
+            GET_LOCAL(_) => "GET_LOCAL",
+            SET_GLOBAL(_) => "SET_GLOBAL",
+            SET_LOCAL(_) => "SET_LOCAL",
+            JUMP_IF_FALSE(_) => "JUMP_IF_FALSE",
+            JUMP(_) => "JUMP",
+            ASSERT => "ASSERT",
+            ASSERT_EQ => "ASSERT_EQ",
+            CALL(_) => "CALL",
+            INDEX => "INDEX",
+            INVOKE(..) => "INVOKE",
+        }
+    }
+
+    /// Byte form: the tag, then the operand (little endian).
+    /// Every op has one operand size, see [`OpCode::operand_len`]. Forward jumps are patched in
+    /// place once the target is known, so a jump can't get longer than its placeholder
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        use OpCode::*;
+        let tag = match self {
+            RETURN => 0,
+            CONSTANT(_) => 1,
+            NEGATE => 2,
+            NOT => 3,
+            NIL => 4,
+            TRUE => 5,
+            FALSE => 6,
+            EQUAL => 7,
+            LESS => 8,
+            GREATER => 9,
+            ADD => 10,
+            SUB => 11,
+            MUL => 12,
+            DIV => 13,
+            AND => 14,
+            OR => 15,
+            PRINT => 16,
+            POP => 17,
+            DEFINE_GLOBAL(_) => 18,
+            GET_GLOBAL(_) => 19,
+            GET_LOCAL(_) => 20,
+            SET_GLOBAL(_) => 21,
+            SET_LOCAL(_) => 22,
+            JUMP_IF_FALSE(_) => 23,
+            JUMP(_) => 24,
+            ASSERT => 25,
+            ASSERT_EQ => 26,
+            CALL(_) => 27,
+            INDEX => 28,
+            INVOKE(..) => 29,
+        };
+        out.push(tag);
+        match self {
+            CONSTANT(idx) | DEFINE_GLOBAL(idx) | GET_GLOBAL(idx) | GET_LOCAL(idx)
+            | SET_GLOBAL(idx) | SET_LOCAL(idx) | CALL(idx) => out.push(*idx),
+            JUMP_IF_FALSE(addr) | JUMP(addr) => out.extend_from_slice(&addr.to_le_bytes()),
+            INVOKE(name, argc) => out.extend_from_slice(&[*name, *argc]),
+            _ => {}
+        }
+    }
+
+    /// Read back an op written by [`OpCode::encode`], with the number of bytes it took.
+    /// `None` for an unknown tag or a missing operand
+    #[inline(always)]
+    pub fn decode(bytes: &[u8]) -> Option<(OpCode, usize)> {
+        use OpCode::*;
+        let idx = || bytes.get(1).copied();
+        let addr = || {
+            Some(InstructAddr::from_le_bytes([
+                *bytes.get(1)?,
+                *bytes.get(2)?,
+            ]))
+        };
+        let res = match *bytes.first()? {
+            0 => (RETURN, 1),
+            1 => (CONSTANT(idx()?), 2),
+            2 => (NEGATE, 1),
+            3 => (NOT, 1),
+            4 => (NIL, 1),
+            5 => (TRUE, 1),
+            6 => (FALSE, 1),
+            7 => (EQUAL, 1),
+            8 => (LESS, 1),
+            9 => (GREATER, 1),
+            10 => (ADD, 1),
+            11 => (SUB, 1),
+            12 => (MUL, 1),
+            13 => (DIV, 1),
+            14 => (AND, 1),
+            15 => (OR, 1),
+            16 => (PRINT, 1),
+            17 => (POP, 1),
+            18 => (DEFINE_GLOBAL(idx()?), 2),
+            19 => (GET_GLOBAL(idx()?), 2),
+            20 => (GET_LOCAL(idx()?), 2),
+            21 => (SET_GLOBAL(idx()?), 2),
+            22 => (SET_LOCAL(idx()?), 2),
+            23 => (JUMP_IF_FALSE(addr()?), 3),
+            24 => (JUMP(addr()?), 3),
+            25 => (ASSERT, 1),
+            26 => (ASSERT_EQ, 1),
+            27 => (CALL(idx()?), 2),
+            28 => (INDEX, 1),
+            29 => (INVOKE(idx()?, *bytes.get(2)?), 3),
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    /// [`OpCode::decode`] for code the verifier passed, where every tag is known and every
+    /// operand is there. Nothing is checked, so this is what the VM dispatches on.
+    /// Panics on code that wasn't verified
+    #[inline(always)]
+    pub fn decode_verified(bytes: &[u8]) -> (OpCode, usize) {
+        use OpCode::*;
+        match bytes[0] {
+            0 => (RETURN, 1),
+            1 => (CONSTANT(bytes[1]), 2),
+            2 => (NEGATE, 1),
+            3 => (NOT, 1),
+            4 => (NIL, 1),
+            5 => (TRUE, 1),
+            6 => (FALSE, 1),
+            7 => (EQUAL, 1),
+            8 => (LESS, 1),
+            9 => (GREATER, 1),
+            10 => (ADD, 1),
+            11 => (SUB, 1),
+            12 => (MUL, 1),
+            13 => (DIV, 1),
+            14 => (AND, 1),
+            15 => (OR, 1),
+            16 => (PRINT, 1),
+            17 => (POP, 1),
+            18 => (DEFINE_GLOBAL(bytes[1]), 2),
+            19 => (GET_GLOBAL(bytes[1]), 2),
+            20 => (GET_LOCAL(bytes[1]), 2),
+            21 => (SET_GLOBAL(bytes[1]), 2),
+            22 => (SET_LOCAL(bytes[1]), 2),
+            23 => (JUMP_IF_FALSE(u16::from_le_bytes([bytes[1], bytes[2]])), 3),
+            24 => (JUMP(u16::from_le_bytes([bytes[1], bytes[2]])), 3),
+            25 => (ASSERT, 1),
+            26 => (ASSERT_EQ, 1),
+            27 => (CALL(bytes[1]), 2),
+            28 => (INDEX, 1),
+            29 => (INVOKE(bytes[1], bytes[2]), 3),
+            tag => unreachable!("op tag {} in verified code", tag),
+        }
+    }
+
+    /// Size of the operand in bytes
+    pub fn operand_len(&self) -> usize {
+        use OpCode::*;
+        match self {
+            CONSTANT(_) | DEFINE_GLOBAL(_) | GET_GLOBAL(_) | GET_LOCAL(_) | SET_GLOBAL(_)
+            | SET_LOCAL(_) | CALL(_) => 1,
+            JUMP_IF_FALSE(_) | JUMP(_) | INVOKE(..) => 2,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trip() {
+        let ops = [
+            OpCode::RETURN,
+            OpCode::CONSTANT(7),
+            OpCode::SET_LOCAL(255),
+            OpCode::JUMP_IF_FALSE(0x1234),
+            OpCode::ASSERT_EQ,
+            OpCode::CALL(2),
+            OpCode::INVOKE(3, 1),
+        ];
+        let mut bytes = vec![];
+        for op in ops {
+            op.encode(&mut bytes);
+        }
+        assert_eq!(bytes.len(), 1 + 2 + 2 + 3 + 1 + 2 + 3);
+
+        let mut pos = 0;
+        for op in ops {
+            let (decoded, len) = OpCode::decode(&bytes[pos..]).unwrap();
+            assert_eq!(decoded.name(), op.name());
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", op));
+            let (fast, fast_len) = OpCode::decode_verified(&bytes[pos..]);
+            assert_eq!(format!("{:?}", fast), format!("{:?}", op));
+            assert_eq!(fast_len, len);
+            pos += len;
+        }
+        // both decoders agree on every tag
+        for tag in 0..=29 {
+            let bytes = [tag, 5, 6];
+            let (op, len) = OpCode::decode(&bytes).unwrap();
+            let (fast, fast_len) = OpCode::decode_verified(&bytes);
+            assert_eq!(format!("{:?}", fast), format!("{:?}", op));
+            assert_eq!(fast_len, len);
+        }
+        assert!(OpCode::decode(&[30]).is_none());
+        assert!(OpCode::decode(&[1]).is_none());
+        assert!(OpCode::decode(&[200]).is_none());
+    }
 }
@@ -1,6 +1,6 @@
-use std::{error, iter::Peekable, str::Chars};
+use std::{iter::Peekable, ops::Range, str::Chars};
 
-use crate::{utils::cite_span, Token, TokenType};
+use crate::{utils::cite_span, InstructAddr, Token, TokenType};
 use thiserror::Error;
 
 type COMPError<T> = Result<T, CompileError>;
@@ -10,11 +10,15 @@ pub enum CompileError {
     #[error("Source code must be ASCII chars only")]
     NonASCIIChar,
     #[error("Syntax Error: {0} \n\t {1}")]
-    SyntaxError(String, String),
+    SyntaxError(String, String, Range<usize>),
     #[error("Expected Token {0:?} found Token {1:?} \n {2}")]
-    UnexpectedToken(TokenType, TokenType, String),
+    UnexpectedToken(TokenType, TokenType, String, Range<usize>),
     #[error("Constant is indexed by u8")]
     ToManyConstants,
+    #[error("Jump too large, jumps only reach the first {} bytes of code", InstructAddr::MAX as usize + 1)]
+    JumpTooLarge,
+    #[error("Variable {0} is already declared in this scope")]
+    Redeclared(String, Range<usize>),
 }
 
 impl CompileError {
@@ -24,7 +28,11 @@ impl CompileError {
     }
 
     pub fn syntax(source: &[u8], msg: &str, st_pos: usize, en_pos: usize) -> Self {
-        Self::SyntaxError(msg.to_string(), Self::prep_cite(source, st_pos, en_pos))
+        Self::SyntaxError(
+            msg.to_string(),
+            Self::prep_cite(source, st_pos, en_pos),
+            st_pos..en_pos,
+        )
     }
 
     pub fn unexpected(
@@ -35,7 +43,28 @@ impl CompileError {
         en_pos: usize,
     ) -> Self {
         let cite = Self::prep_cite(source, st_pos, en_pos);
-        Self::UnexpectedToken(exp, tok, cite)
+        Self::UnexpectedToken(exp, tok, cite, st_pos..en_pos)
+    }
+
+    /// Where in the source the error is, if we know
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Self::SyntaxError(_, _, span)
+            | Self::UnexpectedToken(_, _, _, span)
+            | Self::Redeclared(_, span) => Some(span.clone()),
+            Self::NonASCIIChar | Self::ToManyConstants | Self::JumpTooLarge => None,
+        }
+    }
+
+    /// One line description, without the source citation
+    pub fn message(&self) -> String {
+        match self {
+            Self::SyntaxError(msg, _, _) => msg.clone(),
+            Self::UnexpectedToken(exp, tok, _, _) => {
+                format!("Expected Token {:?} found Token {:?}", exp, tok)
+            }
+            e => e.to_string(),
+        }
     }
 }
 
@@ -45,12 +74,17 @@ pub struct Scanner<'a> {
     pub start_pos: usize,
     pub cur_pos: usize,
     pub line: u32,
+    /// where the current line starts
+    line_start: usize,
+    /// hand out whitespace, newlines and comments as tokens instead of skipping them
+    keep_trivia: bool,
 }
 
 enum MatchState {
     SyntaxError,
     ScanNext,
     ScanNextLine,
+    ScanComment,
     Matched(Token),
 }
 
@@ -67,6 +101,7 @@ macro_rules! next_is_or {
 }
 
 impl<'a> Scanner<'a> {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(source: &'a str) -> COMPError<Self> {
         if !source.is_ascii() {
             return Err(CompileError::NonASCIIChar);
@@ -77,10 +112,26 @@ impl<'a> Scanner<'a> {
             start_pos: 0,
             cur_pos: 0,
             line: 1,
+            line_start: 0,
             chars: source.chars().peekable(),
+            keep_trivia: false,
         })
     }
 
+    /// Scanner that doesn't throw anything away. Whitespace, newlines and comments come back as tokens,
+    /// so concatenating all the lexemes gives back the source.
+    pub fn lossless(source: &'a str) -> COMPError<Self> {
+        let mut scanner = Self::from_str(source)?;
+        scanner.keep_trivia = true;
+        Ok(scanner)
+    }
+
+    /// Raw source text of the last scanned token (strings keep their quotes)
+    pub fn lexeme(&self) -> &'a str {
+        let ascii_chars: &'a [u8] = self.ascii_chars;
+        std::str::from_utf8(&ascii_chars[self.start_pos..self.cur_pos]).unwrap_or("")
+    }
+
     pub fn scan_token(&mut self) -> COMPError<Token> {
         use TokenType::*;
 
@@ -98,11 +149,22 @@ impl<'a> Scanner<'a> {
                     match self._token_matcher(ch) {
                         MatchState::Matched(tok) => return Ok(tok),
                         MatchState::ScanNextLine => {
+                            let tok = self.make_token(Newline);
                             self.line += 1;
+                            self.line_start = self.cur_pos;
+                            if self.keep_trivia {
+                                return Ok(tok);
+                            }
                             self.start_pos = self.cur_pos;
                             continue 'eval_loop;
                         }
-                        MatchState::ScanNext => {
+                        MatchState::ScanNext if self.keep_trivia => {
+                            return Ok(self.make_token(Whitespace))
+                        }
+                        MatchState::ScanComment if self.keep_trivia => {
+                            return Ok(self.make_token(Comment))
+                        }
+                        MatchState::ScanNext | MatchState::ScanComment => {
                             self.start_pos = self.cur_pos;
                             continue 'eval_loop;
                         }
@@ -134,8 +196,22 @@ impl<'a> Scanner<'a> {
         std::str::from_utf8(chars).map_err(|_| CompileError::NonASCIIChar)
     }
 
+    /// 1 based column of the token that is being scanned
+    pub fn col(&self) -> u32 {
+        let line_start = if self.start_pos >= self.line_start {
+            self.line_start
+        } else {
+            // a string that spans lines, the token starts on an earlier line
+            self.ascii_chars[..self.start_pos]
+                .iter()
+                .rposition(|&ch| ch == b'\n')
+                .map_or(0, |nl| nl + 1)
+        };
+        (self.start_pos - line_start) as u32 + 1
+    }
+
     fn make_token(&self, tok_type: TokenType) -> Token {
-        Token::make(tok_type, &self)
+        Token::make(tok_type, self)
     }
 
     fn next_is(&mut self, ch: char) -> bool {
@@ -150,7 +226,7 @@ impl<'a> Scanner<'a> {
 
     fn keyword_or_ident(&self, st: usize, en: usize) -> Token {
         // the shortest keyword is 2 chars
-        if en - st < 1 {
+        if en - st < 2 {
             return self.make_token(TokenType::Ident);
         }
 
@@ -161,7 +237,7 @@ impl<'a> Scanner<'a> {
             if tgt
                 .as_bytes()
                 .iter()
-                .eq(self.ascii_chars[st + 1..en].into_iter())
+                .eq(self.ascii_chars[st + 1..en].iter())
             {
                 self.make_token(kw)
             } else {
@@ -170,7 +246,11 @@ impl<'a> Scanner<'a> {
         };
 
         match first_char {
-            'a' => match_rest("nd", TokenType::And),
+            'a' => match en - st {
+                3 => match_rest("nd", TokenType::And),
+                6 => match_rest("ssert", TokenType::Assert),
+                _ => match_rest("ssert_eq", TokenType::AssertEq),
+            },
             'c' => match_rest("lass", TokenType::Class),
             'e' => match_rest("lse", TokenType::Else),
             'i' => match_rest("f", TokenType::If),
@@ -189,6 +269,7 @@ impl<'a> Scanner<'a> {
             },
             't' => match second_char {
                 'h' => match_rest("his", TokenType::This),
+                'e' => match_rest("est", TokenType::Test),
                 'r' => match_rest("rue", TokenType::True),
                 _ => self.make_token(TokenType::Ident),
             },
@@ -219,6 +300,8 @@ impl<'a> Scanner<'a> {
             ')' => Matched(self.make_token(RightParen)),
             '{' => Matched(self.make_token(LeftBrace)),
             '}' => Matched(self.make_token(RightBrace)),
+            '[' => Matched(self.make_token(LeftBracket)),
+            ']' => Matched(self.make_token(RightBracket)),
             ';' => Matched(self.make_token(Semicolon)),
             ',' => Matched(self.make_token(Comma)),
             '.' => Matched(self.make_token(Dot)),
@@ -226,16 +309,26 @@ impl<'a> Scanner<'a> {
             '+' => Matched(self.make_token(Plus)),
             '/' => {
                 if self.next_is('/') {
+                    // comment runs until the end of the line (or the file)
                     while !self.next_is('\n') {
                         if self.move_to_next_char().is_none() {
-                            return Matched(self.make_token(EoF));
+                            break;
                         }
                     }
-                    ScanNext
+                    ScanComment
                 } else {
                     Matched(self.make_token(Slash))
                 }
             }
+            // `#!` on the first line lets scripts run straight from the shell, it's just a comment to us
+            '#' if self.start_pos == 0 && self.next_is('!') => {
+                while !self.next_is('\n') {
+                    if self.move_to_next_char().is_none() {
+                        break;
+                    }
+                }
+                ScanComment
+            }
             '*' => Matched(self.make_token(Star)),
             '!' => next_is_or!('=', BangEqual, Bang, self),
             '=' => next_is_or!('=', EqualEqual, Equal, self),
@@ -248,6 +341,7 @@ impl<'a> Scanner<'a> {
                         None => return SyntaxError,
                         Some('\n') => {
                             self.line += 1;
+                            self.line_start = self.cur_pos;
                         }
                         _ => {}
                     }
@@ -256,7 +350,12 @@ impl<'a> Scanner<'a> {
                 Matched(self.make_token(String))
             }
             '\n' => ScanNextLine,
-            ' ' | '\t' => ScanNext,
+            ' ' | '\t' => {
+                while let Some(&(' ' | '\t')) = self.peek() {
+                    self.move_to_next_char();
+                }
+                ScanNext
+            }
             _ => SyntaxError,
         }
     }
@@ -7,6 +7,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -41,8 +43,49 @@ pub enum TokenType {
     True,
     Var,
     While,
+    Test,
+    Assert,
+    AssertEq,
     Error,
     EoF,
+    // trivia, only produced by a lossless scanner
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+impl TokenType {
+    pub fn is_keyword(&self) -> bool {
+        use TokenType::*;
+        matches!(
+            self,
+            And | Class
+                | Else
+                | False
+                | For
+                | Fun
+                | If
+                | Nil
+                | Or
+                | Print
+                | Return
+                | Super
+                | This
+                | True
+                | Var
+                | While
+                | Test
+                | Assert
+                | AssertEq
+        )
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Whitespace | TokenType::Newline | TokenType::Comment
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -51,6 +94,8 @@ pub struct Token {
     pub start_pos: usize,
     pub len: usize,
     pub line: u32,
+    /// 1 based column where the token starts, the opening quote for strings
+    pub col: u32,
 }
 
 impl Token {
@@ -68,6 +113,7 @@ impl Token {
             start_pos,
             len,
             line: scanner.line,
+            col: scanner.col(),
         }
     }
 
@@ -77,6 +123,7 @@ impl Token {
             start_pos: 0,
             len: 0,
             line,
+            col: 0,
         }
     }
 }
@@ -109,7 +156,7 @@ impl From<TokenType> for Precedence {
             Greater | GreaterEqual | Less | LessEqual => Self::Comparison,
             Plus | Minus => Self::Term, // what happens in unary setting with minus?
             Star | Slash => Self::Factor,
-            Dot => Self::Call,
+            Dot | LeftParen | LeftBracket => Self::Call,
             _ => Self::None,
         }
     }
@@ -5,6 +5,7 @@ pub struct Liner {
 pub struct Span {
     pub line: u32,
     pub ch_in_line: u32,
+    #[allow(dead_code)]
     pub abs_ch: u32,
 }
 
@@ -36,7 +37,7 @@ impl Liner {
 
         Span {
             line: pos as u32 + 1,
-            ch_in_line: ch_in_line,
+            ch_in_line,
             abs_ch: abs_pos,
         }
     }
@@ -48,42 +49,60 @@ impl From<&str> for Liner {
     }
 }
 
+/// 1 based (line, column) of an absolute char offset
+pub fn line_col(source: &str, pos: usize) -> (u32, u32) {
+    let span = Liner::from(source).get_span(pos);
+    // on the first line `ch_in_line` counts from 0, on the others it counts from the newline char
+    let col = if span.line == 1 {
+        span.ch_in_line + 1
+    } else {
+        span.ch_in_line
+    };
+    (span.line, col)
+}
+
+/// Absolute char offset of a 1 based (line, column), the reverse of [`line_col`]
+pub fn offset_of(source: &str, line: u32, col: u32) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1) as usize)
+        .map(str::len)
+        .sum();
+    line_start + col.saturating_sub(1) as usize
+}
+
 pub fn cite_span(source: &str, st_pos: usize, en_pos: usize) -> String {
-    let lin = Liner::from(source);
-    let st = lin.get_span(st_pos);
-    let en = lin.get_span(en_pos);
+    let (low, st_col) = line_col(source, st_pos);
+    let (high, en_col) = line_col(source, en_pos);
+    let (st_col, en_col) = (st_col - 1, en_col - 1);
 
     let mut res = String::new();
 
     for (line_num_less_one, l) in source.lines().enumerate() {
         let lino = line_num_less_one as u32 + 1;
-        let low = st.line;
-        let high = en.line;
 
         if lino >= low && lino <= high {
             res.push_str(&format!(" {} | {}", lino, l));
-            res.push_str("\n");
+            res.push('\n');
 
             res.push_str("   | ");
 
-            if lino == low {
-                for _ in 0..st.ch_in_line {
-                    res.push_str(" ");
-                }
-                for _ in 0..(l.len() as u32 - st.ch_in_line) {
-                    res.push_str("^");
-                }
-                res.push_str("\n");
+            let (from, to) = if lino == low && lino == high {
+                (st_col, en_col.max(st_col + 1))
+            } else if lino == low {
+                (st_col, l.len() as u32)
             } else if lino == high {
-                for _ in 0..en.ch_in_line {
-                    res.push_str("^");
-                }
-                res.push_str("\n");
+                (0, en_col)
             } else {
-                for _ in 0..l.len() {
-                    res.push_str("^");
-                }
+                (0, l.len() as u32)
+            };
+            for _ in 0..from {
+                res.push(' ');
+            }
+            for _ in from..to {
+                res.push('^');
             }
+            res.push('\n');
         }
     }
     res
@@ -92,10 +111,10 @@ pub fn cite_span(source: &str, st_pos: usize, en_pos: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    const txt: &str = "hello\ni\nlove you\nwont you tell me your name";
+    const TXT: &str = "hello\ni\nlove you\nwont you tell me your name";
 
     fn liner_helper(pos: usize, cor_line: u32, cor_ch: u32) {
-        let liner: Liner = txt.into();
+        let liner: Liner = TXT.into();
         let span = liner.get_span(pos);
 
         assert_eq!(span.line, cor_line);
@@ -106,18 +125,18 @@ mod tests {
         use std::fs;
 
         let source =
-            fs::read_to_string("/Users/gregoryaxler/Desktop/projects/gaxler/rs-lox/expr.lox")
-                .unwrap();
+            fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../expr.lox")).unwrap();
         let res = cite_span(&source, 32, 34);
         println!("{}", res);
     }
     // #[test]
+    #[allow(dead_code)]
     fn line_ann() {
-        let lin: Liner = txt.into();
+        let lin: Liner = TXT.into();
         let st = lin.get_span(7);
         let en = lin.get_span(20);
 
-        for (line_num_less_one, l) in txt.lines().enumerate() {
+        for (line_num_less_one, l) in TXT.lines().enumerate() {
             let lino = line_num_less_one as u32 + 1;
             let low = st.line;
             let high = en.line;
@@ -142,10 +161,21 @@ mod tests {
                     }
                 }
 
-                println!("");
+                println!();
             }
         }
     }
+    #[test]
+    fn line_col_positions() {
+        assert_eq!(line_col(TXT, 0), (1, 1));
+        assert_eq!(line_col(TXT, 6), (2, 1));
+        assert_eq!(line_col(TXT, 10), (3, 3));
+        for pos in [0, 6, 10, 20] {
+            let (line, col) = line_col(TXT, pos);
+            assert_eq!(offset_of(TXT, line, col), pos);
+        }
+    }
+
     #[test]
     fn liner_in_first() {
         liner_helper(2, 1u32, 2u32);
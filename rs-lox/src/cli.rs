@@ -0,0 +1,451 @@
+//! The `rs-lox` command line, see [`USAGE`]. This runs programs, the tools (`fmt`, `lint`,
+//! `test`, ...) are dispatched from `main` to their own modules.
+//!
+//! Exit codes are the ones of the Lox reference implementation, taken from sysexits.h.
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Instant;
+
+use compiler::ast::AstDump;
+use values::loxc::is_compiled;
+use values::Chunk;
+
+use crate::disasm::disassemble;
+use crate::errors::RuntimeError;
+use crate::session::RuntimeContext;
+use crate::{coverage, loxc, profile, repl};
+
+/// the tool ran but found problems: failing tests, lint errors, files that need formatting
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_COMPILE: i32 = 65;
+pub const EXIT_RUNTIME: i32 = 70;
+pub const EXIT_IO: i32 = 74;
+
+const PROFILE_FILE: &str = "profile.folded";
+
+pub const USAGE: &str = "\
+usage: rs-lox [options] [<script> | - | -e <code>] [args...]
+       rs-lox <command> ...
+
+Without a script the REPL starts, `-` reads the script from stdin. A script is lox source or a
+file made by `rs-lox compile`. The arguments after the script are the `args` list of the script.
+
+commands:
+  run [options] <script> [args...]   run a script, the same as without a command
+  repl                               start the REPL
+  disasm <script>                    print the bytecode of a script
+  check <script>...                  compile without running and report the errors
+  compile, test, fmt, lint, debug, lsp, dap
+                                     the tools, run them without arguments for their usage
+
+options:
+  -e <code>             run <code> instead of a script
+  --trace               print every op and the stack while running
+  --coverage[=<file>]   record the lines that ran, in .lox-coverage by default
+  --profile[=<file>]    profile the run, the stacks go to profile.folded by default
+  --dump-ast            print the syntax tree instead of running
+  --version             print the version
+  -h, --help            print this help
+
+exit codes: 64 bad usage, 65 compile error, 70 runtime error, 74 a file can't be read or written,
+or whatever the script passed to `exit(code)`. The tools exit with 1 when they ran but found
+problems: failing tests, lint errors or files that need formatting";
+
+/// Where the program comes from
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    File(String),
+    Stdin,
+    Code(String),
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    trace: bool,
+    coverage: Option<String>,
+    profile: Option<String>,
+    dump_ast: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run {
+        input: Input,
+        opts: Options,
+        args: Vec<String>,
+    },
+    Repl,
+    Disasm(Input),
+    Check(Vec<String>),
+    Version,
+    Help,
+}
+
+pub fn run(argv: &[String]) -> i32 {
+    let cmd = match parse(argv) {
+        Ok(cmd) => cmd,
+        Err(msg) => {
+            eprintln!("rs-lox: {}, see `rs-lox --help`", msg);
+            return EXIT_USAGE;
+        }
+    };
+    match cmd {
+        Command::Run { input, opts, args } => run_program(&input, &opts, args),
+        Command::Repl => repl::run(),
+        Command::Disasm(input) => disasm(&input),
+        Command::Check(paths) => check(&paths),
+        Command::Version => {
+            println!("rs-lox {}", env!("CARGO_PKG_VERSION"));
+            0
+        }
+        Command::Help => {
+            println!("{}", USAGE);
+            0
+        }
+    }
+}
+
+fn parse(argv: &[String]) -> Result<Command, String> {
+    match argv.first().map(|a| a.as_str()) {
+        Some("run") => parse_run(&argv[1..], true),
+        Some("repl") if argv.len() == 1 => Ok(Command::Repl),
+        Some("repl") => Err("repl takes no arguments".to_string()),
+        Some("disasm") => match &argv[1..] {
+            [script] => Ok(Command::Disasm(input_of(script))),
+            _ => Err("disasm takes one script".to_string()),
+        },
+        Some("check") if argv.len() > 1 => Ok(Command::Check(argv[1..].to_vec())),
+        Some("check") => Err("check needs a script".to_string()),
+        _ => parse_run(argv, false),
+    }
+}
+
+/// Options, then the script and its arguments. Without a script it's the REPL, unless
+/// the script is required
+fn parse_run(argv: &[String], script_required: bool) -> Result<Command, String> {
+    let mut opts = Options::default();
+    let mut argv = argv.iter();
+    let input = loop {
+        let Some(arg) = argv.next() else {
+            if script_required || opts != Options::default() {
+                return Err("no script to run".to_string());
+            }
+            return Ok(Command::Repl);
+        };
+        match arg.as_str() {
+            "-e" => match argv.next() {
+                Some(code) => break Input::Code(code.clone()),
+                None => return Err("-e needs some code".to_string()),
+            },
+            "--trace" => opts.trace = true,
+            "--dump-ast" => opts.dump_ast = true,
+            "--coverage" => opts.coverage = Some(coverage::DATA_FILE.to_string()),
+            "--profile" => opts.profile = Some(PROFILE_FILE.to_string()),
+            "--version" => return Ok(Command::Version),
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => match argv.next() {
+                Some(script) => break Input::File(script.clone()),
+                None => return Err("no script after --".to_string()),
+            },
+            arg => {
+                if let Some(file) = arg.strip_prefix("--coverage=") {
+                    opts.coverage = Some(file.to_string());
+                } else if let Some(file) = arg.strip_prefix("--profile=") {
+                    opts.profile = Some(file.to_string());
+                } else if arg.starts_with('-') && arg != "-" {
+                    return Err(format!("unknown option `{}`", arg));
+                } else {
+                    break input_of(arg);
+                }
+            }
+        }
+    };
+    // both want to be the VM hook, there is only one
+    if opts.profile.is_some() && opts.coverage.is_some() {
+        return Err("--profile and --coverage can't be used together".to_string());
+    }
+    Ok(Command::Run {
+        input,
+        opts,
+        args: argv.cloned().collect(),
+    })
+}
+
+fn input_of(arg: &str) -> Input {
+    match arg {
+        "-" => Input::Stdin,
+        path => Input::File(path.to_string()),
+    }
+}
+
+/// The name to report errors with, and the contents
+fn read(input: &Input) -> Result<(String, Vec<u8>), i32> {
+    let (name, res) = match input {
+        Input::File(path) => (path.clone(), fs::read(path)),
+        Input::Stdin => {
+            let mut bytes = vec![];
+            let res = io::stdin().read_to_end(&mut bytes).map(|_| bytes);
+            ("<stdin>".to_string(), res)
+        }
+        Input::Code(code) => ("<-e>".to_string(), Ok(code.clone().into_bytes())),
+    };
+    match res {
+        Ok(bytes) => Ok((name, bytes)),
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            Err(EXIT_IO)
+        }
+    }
+}
+
+fn run_program(input: &Input, opts: &Options, args: Vec<String>) -> i32 {
+    let (name, bytes) = match read(input) {
+        Ok(read) => read,
+        Err(code) => return code,
+    };
+    if opts.dump_ast {
+        return dump_ast(&name, bytes);
+    }
+
+    let mut runtime = RuntimeContext::start(opts.trace);
+    runtime.set_args(args);
+    let (addr, source) = match loxc::load(&mut runtime, &name, bytes) {
+        Ok(loaded) => loaded,
+        Err(code) => return code,
+    };
+
+    let res = if let Some(out) = opts.profile.as_ref() {
+        let mut profiler = profile::Profiler::default();
+        let start = Instant::now();
+        let res = runtime.exec_hooked(addr, &mut profiler);
+        let wall = start.elapsed();
+        eprint!("{}", profiler.report(source.as_deref().unwrap_or(""), wall));
+        match fs::write(out, profiler.collapsed(&name)) {
+            Ok(_) => eprintln!("\ncollapsed stacks written to {}", out),
+            Err(e) => eprintln!("\nfailed to write {}: {}", out, e),
+        }
+        res
+    } else if let Some(data_file) = opts.coverage.as_ref() {
+        let mut rec = coverage::Recorder::default();
+        let res = runtime.exec_hooked(addr, &mut rec);
+        let chunk = runtime.get_chunk(addr);
+        let cov = coverage::FileCoverage::new(&chunk, &rec);
+        match coverage::save(Path::new(data_file), &name, cov) {
+            Ok(all) => eprint!("{}", all.summary()),
+            Err(e) => eprintln!("failed to write coverage to {}: {}", data_file, e),
+        }
+        res
+    } else {
+        runtime.exec(addr)
+    };
+
+    match res {
+        Ok(_) => 0,
+        Err(RuntimeError::Exit(code)) => code,
+        Err(e) => {
+            // compiled files don't come with their source, errors only get a line and column then
+            runtime.report(&e, source.as_deref());
+            EXIT_RUNTIME
+        }
+    }
+}
+
+fn dump_ast(name: &str, bytes: Vec<u8>) -> i32 {
+    if is_compiled(&bytes) {
+        eprintln!("{}: a compiled file has no syntax tree", name);
+        return EXIT_USAGE;
+    }
+    let Ok(source) = String::from_utf8(bytes) else {
+        eprintln!("{}: not a lox source file", name);
+        return EXIT_COMPILE;
+    };
+    match RuntimeContext::start(false).parse(&source) {
+        Ok(program) => {
+            print!("{}", AstDump(&program));
+            0
+        }
+        Err(e) => {
+            eprintln!(" Error: [\n\t {} \n]", e);
+            EXIT_COMPILE
+        }
+    }
+}
+
+fn disasm(input: &Input) -> i32 {
+    let (name, bytes) = match read(input) {
+        Ok(read) => read,
+        Err(code) => return code,
+    };
+    let mut runtime = RuntimeContext::start(false);
+    match loxc::load(&mut runtime, &name, bytes) {
+        Ok((addr, _)) => {
+            print!("{}", disassemble(&runtime.get_chunk(addr)));
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// Compile every script without running it. Compiled scripts go through the verifier
+fn check(paths: &[String]) -> i32 {
+    let mut code = 0;
+    for path in paths {
+        let (name, bytes) = match read(&Input::File(path.clone())) {
+            Ok(read) => read,
+            Err(c) => {
+                code = code.max(c);
+                continue;
+            }
+        };
+        let res = if is_compiled(&bytes) {
+            Chunk::from_bytes(&bytes)
+                .map_err(|e| e.to_string())
+                .and_then(|chunk| chunk.verify().map_err(|e| e.to_string()))
+        } else {
+            match String::from_utf8(bytes) {
+                Ok(source) => RuntimeContext::start(false)
+                    .compile(&source)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                Err(_) => Err("not a lox source file".to_string()),
+            }
+        };
+        if let Err(e) = res {
+            eprintln!("{}: {}", name, e);
+            code = code.max(EXIT_COMPILE);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(argv: &str) -> Vec<String> {
+        argv.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parsing() {
+        let run = |input, opts, script_args: &str| Command::Run {
+            input,
+            opts,
+            args: args(script_args),
+        };
+        let file = |path: &str| Input::File(path.to_string());
+
+        assert_eq!(parse(&[]), Ok(Command::Repl));
+        assert_eq!(parse(&args("repl")), Ok(Command::Repl));
+        assert_eq!(
+            parse(&args("a.lox x --trace")),
+            Ok(run(file("a.lox"), Options::default(), "x --trace"))
+        );
+        let traced = Options {
+            trace: true,
+            coverage: Some("cov".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            parse(&args("run --trace --coverage=cov - 1 2")),
+            Ok(run(Input::Stdin, traced, "1 2"))
+        );
+        assert_eq!(
+            parse(&["-e".to_string(), "print 1;".to_string()]),
+            Ok(run(
+                Input::Code("print 1;".to_string()),
+                Options::default(),
+                ""
+            ))
+        );
+        assert_eq!(
+            parse(&args("-- -odd.lox")),
+            Ok(run(file("-odd.lox"), Options::default(), ""))
+        );
+        assert_eq!(parse(&args("disasm -")), Ok(Command::Disasm(Input::Stdin)));
+        assert_eq!(
+            parse(&args("check a.lox b.lox")),
+            Ok(Command::Check(args("a.lox b.lox")))
+        );
+        assert_eq!(parse(&args("--version")), Ok(Command::Version));
+        assert_eq!(parse(&args("-h")), Ok(Command::Help));
+
+        for bad in [
+            "run",
+            "--trace",
+            "-e",
+            "--nope a.lox",
+            "disasm",
+            "check",
+            "repl x",
+            "--profile --coverage a.lox",
+        ] {
+            assert!(parse(&args(bad)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let code = |source: &str| run(&["-e".to_string(), source.to_string()]);
+        assert_eq!(code("var a = 1;"), 0);
+        assert_eq!(code("var a = ;"), EXIT_COMPILE);
+        assert_eq!(code("print nope;"), EXIT_RUNTIME);
+        assert_eq!(code("exit(3); print nope;"), 3);
+        assert_eq!(code("exit(\"3\");"), EXIT_RUNTIME);
+        assert_eq!(
+            run(&[
+                "-e".to_string(),
+                "if (args[0] == \"a\" and args[1] == \"b\") exit(5);".to_string(),
+                "a".to_string(),
+                "b".to_string(),
+            ]),
+            5
+        );
+        assert_eq!(run(&args("/no/such/file.lox")), EXIT_IO);
+        assert_eq!(run(&args("--bogus")), EXIT_USAGE);
+
+        let dir = std::env::temp_dir();
+        let good = dir.join("rs-lox-cli-good.lox");
+        let bad = dir.join("rs-lox-cli-bad.lox");
+        fs::write(&good, "var a = 1;").unwrap();
+        fs::write(&bad, "var a = ;").unwrap();
+        let check = |paths: &[&Path]| {
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            super::check(&paths)
+        };
+        assert_eq!(check(&[&good]), 0);
+        assert_eq!(check(&[&good, &bad]), EXIT_COMPILE);
+        assert_eq!(check(&[&bad, Path::new("/no/such/file.lox")]), EXIT_IO);
+        fs::remove_file(&good).unwrap();
+        fs::remove_file(&bad).unwrap();
+    }
+
+    #[test]
+    fn tools_share_exit_codes() {
+        use crate::{debugger, fmt, lint, test_runner};
+
+        let missing = args("/no/such/file.lox");
+        assert_eq!(loxc::run(&[]), EXIT_USAGE);
+        assert_eq!(debugger::run(&[]), EXIT_USAGE);
+        assert_eq!(loxc::run(&missing), EXIT_IO);
+        assert_eq!(debugger::run(&missing), EXIT_IO);
+        assert_eq!(fmt::run(&missing), EXIT_IO);
+        assert_eq!(lint::run(&missing), EXIT_IO);
+        assert_eq!(test_runner::run(&missing), EXIT_FAILURE);
+
+        let bad = std::env::temp_dir().join("rs-lox-cli-tools-bad.lox");
+        fs::write(&bad, "var a = ;").unwrap();
+        let bad_args = args(&bad.to_string_lossy());
+        assert_eq!(loxc::run(&bad_args), EXIT_COMPILE);
+        assert_eq!(lint::run(&bad_args), EXIT_COMPILE);
+        fs::write(&bad, "print   1;").unwrap();
+        let check = [bad_args[0].clone(), "--check".to_string()];
+        assert_eq!(fmt::run(&check), EXIT_FAILURE);
+        fs::remove_file(&bad).unwrap();
+    }
+}
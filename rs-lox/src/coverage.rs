@@ -0,0 +1,262 @@
+//! `rs-lox run --coverage` records which instructions and source lines ran.
+//!
+//! Results are merged into a data file (`.lox-coverage` by default) so several runs add up,
+//! then a summary is printed to stderr and an LCOV report is written next to the data file.
+//! A file whose bytecode changed since the last run starts over.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use values::Chunk;
+
+use crate::runtime::{Control, Hook, VM};
+
+pub const DATA_FILE: &str = ".lox-coverage";
+pub const LCOV_FILE: &str = "lcov.info";
+
+/// Counts how many times every instruction ran, by address
+#[derive(Debug, Default)]
+pub struct Recorder {
+    hits: Vec<u64>,
+}
+
+impl Hook for Recorder {
+    fn before_op(&mut self, vm: &VM) -> Control {
+        let ip = vm.ip();
+        if ip >= self.hits.len() {
+            self.hits.resize(ip + 1, 0);
+        }
+        self.hits[ip] += 1;
+        Control::Continue
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileCoverage {
+    /// hit count per instruction
+    pub ops: Vec<u64>,
+    /// hit count per line that has code
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    pub fn new(chunk: &Chunk, rec: &Recorder) -> Self {
+        let mut ops = vec![];
+        let mut lines = BTreeMap::new();
+        for (ip, _) in chunk.instructions() {
+            let hits = rec.hits.get(ip).copied().unwrap_or(0);
+            ops.push(hits);
+            let count = lines.entry(chunk.get_line_num(ip)).or_insert(0);
+            // a line ran as many times as its busiest instruction
+            *count = (*count).max(hits);
+        }
+        Self { ops, lines }
+    }
+
+    fn merge(&mut self, other: &FileCoverage) {
+        let same_code =
+            self.ops.len() == other.ops.len() && self.lines.keys().eq(other.lines.keys());
+        if !same_code {
+            *self = other.clone();
+            return;
+        }
+        for (a, b) in self.ops.iter_mut().zip(other.ops.iter()) {
+            *a += b;
+        }
+        for (line, count) in other.lines.iter() {
+            *self.lines.entry(*line).or_insert(0) += count;
+        }
+    }
+
+    pub fn ops_hit(&self) -> usize {
+        self.ops.iter().filter(|&&c| c > 0).count()
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&c| c > 0).count()
+    }
+
+    pub fn missed_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, &c)| c == 0)
+            .map(|(l, _)| *l)
+            .collect()
+    }
+}
+
+/// Coverage of every file we ran, by path
+#[derive(Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl Coverage {
+    pub fn add(&mut self, path: &str, cov: FileCoverage) {
+        match self.files.get_mut(path) {
+            Some(old) => old.merge(&cov),
+            None => {
+                self.files.insert(path.to_string(), cov);
+            }
+        }
+    }
+
+    /// Data file format, one record per line:
+    /// `file <path>`, then `ops <count>..` and `line <num> <count>` for that file
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+        for (path, cov) in self.files.iter() {
+            let _ = writeln!(out, "file {}", path);
+            let ops: Vec<String> = cov.ops.iter().map(|c| c.to_string()).collect();
+            let _ = writeln!(out, "ops {}", ops.join(" "));
+            for (line, count) in cov.lines.iter() {
+                let _ = writeln!(out, "line {} {}", line, count);
+            }
+        }
+        out
+    }
+
+    /// Broken records are skipped, worst case we lose old results
+    pub fn parse(data: &str) -> Self {
+        let mut res = Coverage::default();
+        let mut cur: Option<(String, FileCoverage)> = None;
+        for l in data.lines() {
+            let (kind, rest) = l.split_once(' ').unwrap_or((l, ""));
+            match (kind, cur.as_mut()) {
+                ("file", _) => {
+                    if let Some((path, cov)) = cur.take() {
+                        res.files.insert(path, cov);
+                    }
+                    cur = Some((rest.to_string(), FileCoverage::default()));
+                }
+                ("ops", Some((_, cov))) => {
+                    cov.ops = rest.split(' ').filter_map(|c| c.parse().ok()).collect();
+                }
+                ("line", Some((_, cov))) => {
+                    let mut nums = rest.split(' ').filter_map(|n| n.parse().ok());
+                    if let (Some(line), Some(count)) = (nums.next(), nums.next()) {
+                        cov.lines.insert(line as usize, count);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some((path, cov)) = cur {
+            res.files.insert(path, cov);
+        }
+        res
+    }
+
+    pub fn lcov(&self) -> String {
+        let mut out = String::new();
+        for (path, cov) in self.files.iter() {
+            let _ = writeln!(out, "TN:\nSF:{}", path);
+            for (line, count) in cov.lines.iter() {
+                let _ = writeln!(out, "DA:{},{}", line, count);
+            }
+            let _ = writeln!(
+                out,
+                "LF:{}\nLH:{}\nend_of_record",
+                cov.lines.len(),
+                cov.lines_hit()
+            );
+        }
+        out
+    }
+
+    pub fn summary(&self) -> String {
+        let pct = |hit: usize, all: usize| hit as f64 * 100.0 / all.max(1) as f64;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            " {:<40} {:>19} {:>19}",
+            "file", "lines", "instructions"
+        );
+        for (path, cov) in self.files.iter() {
+            let (lh, lf) = (cov.lines_hit(), cov.lines.len());
+            let (oh, of) = (cov.ops_hit(), cov.ops.len());
+            let _ = writeln!(
+                out,
+                " {:<40} {:>7}/{:<4} {:>5.1}% {:>7}/{:<4} {:>5.1}%",
+                path,
+                lh,
+                lf,
+                pct(lh, lf),
+                oh,
+                of,
+                pct(oh, of)
+            );
+            let missed = cov.missed_lines();
+            if !missed.is_empty() {
+                let missed: Vec<String> = missed.iter().map(|l| l.to_string()).collect();
+                let _ = writeln!(out, "   not run: {}", missed.join(", "));
+            }
+        }
+        out
+    }
+}
+
+/// Merge the coverage of one run into `data_file` and write the reports. Returns the merged result
+pub fn save(data_file: &Path, path: &str, cov: FileCoverage) -> std::io::Result<Coverage> {
+    let mut all = fs::read_to_string(data_file)
+        .map(|d| Coverage::parse(&d))
+        .unwrap_or_default();
+    all.add(path, cov);
+    fs::write(data_file, all.serialize())?;
+    let lcov = data_file.with_file_name(LCOV_FILE);
+    fs::write(lcov, all.lcov())?;
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::RuntimeContext;
+
+    fn run(source: &str) -> FileCoverage {
+        let mut runtime = RuntimeContext::start(false);
+        let addr = runtime.compile(source).unwrap();
+        let mut rec = Recorder::default();
+        runtime.exec_hooked(addr, &mut rec).unwrap();
+        let chunk = runtime.get_chunk(addr);
+        FileCoverage::new(&chunk, &rec)
+    }
+
+    #[test]
+    fn lines_and_merging() {
+        let source = "var a = 1;\nif (a > 2)\n    print \"big\";\nprint a;\n";
+        let cov = run(source);
+        assert_eq!(cov.missed_lines(), [3]);
+        assert_eq!((cov.lines_hit(), cov.lines.len()), (3, 4));
+        assert!(cov.ops_hit() < cov.ops.len());
+
+        let mut all = Coverage::default();
+        all.add("a.lox", cov.clone());
+        all.add("a.lox", cov);
+        assert_eq!(all.files["a.lox"].lines[&4], 2);
+
+        // the bytecode changed, old counts are dropped
+        all.add("a.lox", run("print 1;"));
+        assert_eq!(all.files["a.lox"].lines[&1], 1);
+        assert_eq!(all.files["a.lox"].lines.len(), 1);
+    }
+
+    #[test]
+    fn data_file_and_reports() {
+        let mut all = Coverage::default();
+        all.add(
+            "a.lox",
+            run("var a = 1;\nif (a > 2)\n    print a;\nprint a;\n"),
+        );
+        all.add("b.lox", run("print 2;"));
+        assert_eq!(Coverage::parse(&all.serialize()), all);
+
+        let lcov = all.lcov();
+        assert!(lcov.starts_with(
+            "TN:\nSF:a.lox\nDA:1,1\nDA:2,1\nDA:3,0\nDA:4,1\nLF:4\nLH:3\nend_of_record\n"
+        ));
+        assert!(lcov.contains("SF:b.lox\nDA:1,1\nLF:1\nLH:1\n"));
+        assert!(all.summary().contains("   not run: 3\n"));
+    }
+}
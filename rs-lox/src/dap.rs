@@ -0,0 +1,380 @@
+//! `rs-lox dap` Debug Adapter Protocol server over stdio.
+//!
+//! There is a single thread and, until Lox grows functions, a single stack frame.
+//! The program is run by the same [`Target`] the command line debugger uses, `print`
+//! output is sent to the client as `output` events.
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use serde_json::{json, Value as Json};
+
+use crate::cli::{EXIT_IO, EXIT_RUNTIME};
+use crate::debugger::{Mode, Target};
+use crate::runtime::Exit;
+use crate::wire::{read_message, write_message};
+
+const THREAD_ID: u64 = 1;
+const LOCALS_REF: u64 = 1;
+const GLOBALS_REF: u64 = 2;
+
+pub fn run() -> i32 {
+    let stdin = io::stdin();
+    let mut adapter = Adapter::new(io::stdout());
+    match adapter.serve(&mut stdin.lock()) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("dap: {}", e);
+            EXIT_IO
+        }
+    }
+}
+
+pub struct Adapter<W: Write> {
+    out: W,
+    seq: u64,
+    target: Option<Target>,
+    program: String,
+    stop_on_entry: bool,
+}
+
+impl<W: Write> Adapter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            target: None,
+            program: String::new(),
+            stop_on_entry: false,
+        }
+    }
+
+    /// Handle requests until `disconnect` or the end of the input
+    pub fn serve(&mut self, input: &mut impl BufRead) -> io::Result<()> {
+        while let Some(msg) = read_message(input)? {
+            if msg["type"] != "request" {
+                continue;
+            }
+            let command = msg["command"].as_str().unwrap_or_default().to_string();
+            let args = &msg["arguments"];
+            let res = self.request(&command, args);
+            match res {
+                Ok(body) => self.respond(&msg, true, body, None)?,
+                Err(e) => self.respond(&msg, false, Json::Null, Some(e))?,
+            }
+            // things that happen after the response went out
+            match command.as_str() {
+                "initialize" => self.event("initialized", json!({}))?,
+                "configurationDone" if self.stop_on_entry => self.run(Mode::Step, "entry")?,
+                "configurationDone" => self.run(Mode::Continue, "breakpoint")?,
+                "continue" => self.run(Mode::Continue, "breakpoint")?,
+                "next" => self.run(Mode::Over(self.depth()), "step")?,
+                "stepIn" => self.run(Mode::Step, "step")?,
+                "stepOut" => self.run(Mode::Out(self.depth()), "step")?,
+                "disconnect" => return Ok(()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        let body = match command {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsEvaluateForHovers": true,
+            }),
+            "launch" => {
+                let program = args["program"].as_str().ok_or("launch needs a `program`")?;
+                let source =
+                    fs::read_to_string(program).map_err(|e| format!("{}: {}", program, e))?;
+                let mut target = Target::load(&source).map_err(|e| e.to_string())?;
+                target.capture_output();
+                self.target = Some(target);
+                self.program = program.to_string();
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Json::Null
+            }
+            "setBreakpoints" => {
+                let target = self.target()?;
+                target.clear_breakpoints();
+                let lines = args["breakpoints"].as_array().cloned().unwrap_or_default();
+                let breakpoints: Vec<Json> = lines
+                    .iter()
+                    .filter_map(|bp| bp["line"].as_u64())
+                    .map(|line| {
+                        let verified = target.add_breakpoint(line as usize);
+                        json!({"verified": verified, "line": line})
+                    })
+                    .collect();
+                json!({ "breakpoints": breakpoints })
+            }
+            "configurationDone" | "next" | "stepIn" | "stepOut" | "disconnect" => Json::Null,
+            "continue" => json!({"allThreadsContinued": true}),
+            "threads" => json!({"threads": [{"id": THREAD_ID, "name": "main"}]}),
+            "stackTrace" => {
+                let line = self.target()?.vm().line();
+                let name = Path::new(&self.program)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                json!({
+                    "stackFrames": [{
+                        "id": 0,
+                        "name": "<script>",
+                        "line": line,
+                        "column": 1,
+                        "source": {"name": name, "path": self.program},
+                    }],
+                    "totalFrames": 1,
+                })
+            }
+            "scopes" => json!({"scopes": [
+                {"name": "Locals", "variablesReference": LOCALS_REF, "expensive": false},
+                {"name": "Globals", "variablesReference": GLOBALS_REF, "expensive": false},
+            ]}),
+            "variables" => {
+                let target = self.target()?;
+                let vars = match args["variablesReference"].as_u64() {
+                    Some(LOCALS_REF) => target.vm().locals(),
+                    Some(GLOBALS_REF) => target.globals(),
+                    _ => vec![],
+                };
+                let vars: Vec<Json> = vars
+                    .iter()
+                    .map(|(name, val)| {
+                        json!({"name": name, "value": val.to_string(), "variablesReference": 0})
+                    })
+                    .collect();
+                json!({ "variables": vars })
+            }
+            "evaluate" => {
+                let expr = args["expression"].as_str().unwrap_or_default();
+                let val = self.target()?.eval(expr)?;
+                json!({"result": val.to_string(), "variablesReference": 0})
+            }
+            _ => return Err(format!("unsupported request {}", command)),
+        };
+        Ok(body)
+    }
+
+    /// Run the program until it stops, then tell the client why
+    fn run(&mut self, mode: Mode, reason: &str) -> io::Result<()> {
+        let Some(target) = self.target.as_mut() else {
+            return Ok(());
+        };
+        if target.finished() {
+            return Ok(());
+        }
+        let res = target.resume(mode);
+        let hit = target.hit_breakpoint();
+        for line in target.take_output() {
+            self.event(
+                "output",
+                json!({"category": "stdout", "output": line + "\n"}),
+            )?;
+        }
+        match res {
+            Ok(Exit::Paused) => {
+                let reason = if hit { "breakpoint" } else { reason };
+                self.event(
+                    "stopped",
+                    json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}),
+                )
+            }
+            Ok(Exit::Done) => self.finish(0),
+            Err(e) => {
+                let output = format!("runtime error: {}\n", e);
+                self.event("output", json!({"category": "stderr", "output": output}))?;
+                self.finish(EXIT_RUNTIME)
+            }
+        }
+    }
+
+    fn finish(&mut self, code: i32) -> io::Result<()> {
+        self.event("exited", json!({ "exitCode": code }))?;
+        self.event("terminated", json!({}))
+    }
+
+    fn depth(&self) -> usize {
+        self.target.as_ref().map_or(0, |t| t.vm().frame_depth())
+    }
+
+    fn target(&mut self) -> Result<&mut Target, String> {
+        self.target
+            .as_mut()
+            .ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn respond(
+        &mut self,
+        req: &Json,
+        success: bool,
+        body: Json,
+        message: Option<String>,
+    ) -> io::Result<()> {
+        self.seq += 1;
+        let mut msg = json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": success,
+            "body": body,
+        });
+        if let Some(message) = message {
+            msg["message"] = Json::String(message);
+        }
+        write_message(&mut self.out, &msg)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.seq += 1;
+        let msg = json!({"seq": self.seq, "type": "event", "event": event, "body": body});
+        write_message(&mut self.out, &msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PROGRAM: &str = "var a = 1;
+{
+    var b = a + 1;
+    print b;
+}
+print a;
+";
+
+    /// Run a scripted session against a program written to a temp file
+    fn session(name: &str, source: &str, requests: &[(&str, Json)]) -> Vec<Json> {
+        let path = std::env::temp_dir().join(format!("rs-lox-dap-{}.lox", name));
+        fs::write(&path, source).unwrap();
+        let mut input = vec![];
+        let mut seq = 0;
+        for (command, args) in requests {
+            seq += 1;
+            let mut args = args.clone();
+            if *command == "launch" {
+                args["program"] = json!(path.to_string_lossy());
+            }
+            let req = json!({"seq": seq, "type": "request", "command": command, "arguments": args});
+            write_message(&mut input, &req).unwrap();
+        }
+        let mut adapter = Adapter::new(vec![]);
+        adapter.serve(&mut Cursor::new(input)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut output = Cursor::new(adapter.out);
+        let mut msgs = vec![];
+        while let Some(msg) = read_message(&mut output).unwrap() {
+            msgs.push(msg);
+        }
+        msgs
+    }
+
+    fn response<'a>(msgs: &'a [Json], command: &str) -> &'a Json {
+        msgs.iter()
+            .find(|m| m["type"] == "response" && m["command"] == command)
+            .unwrap_or_else(|| panic!("no {} response", command))
+    }
+
+    fn events(msgs: &[Json]) -> Vec<String> {
+        msgs.iter()
+            .filter(|m| m["type"] == "event")
+            .map(|m| match m["event"].as_str().unwrap() {
+                "stopped" => format!("stopped:{}", m["body"]["reason"].as_str().unwrap()),
+                "output" => format!("output:{}", m["body"]["output"].as_str().unwrap().trim()),
+                e => e.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn breakpoints_and_variables() {
+        let msgs = session(
+            "breakpoints",
+            PROGRAM,
+            &[
+                ("initialize", json!({"adapterID": "rs-lox"})),
+                ("launch", json!({})),
+                (
+                    "setBreakpoints",
+                    json!({"breakpoints": [{"line": 4}, {"line": 5}]}),
+                ),
+                ("configurationDone", json!({})),
+                ("threads", json!({})),
+                ("stackTrace", json!({"threadId": 1})),
+                ("scopes", json!({"frameId": 0})),
+                ("variables", json!({"variablesReference": LOCALS_REF})),
+                ("evaluate", json!({"expression": "b * 10", "frameId": 0})),
+                ("continue", json!({"threadId": 1})),
+                ("disconnect", json!({})),
+            ],
+        );
+        let bps = &response(&msgs, "setBreakpoints")["body"]["breakpoints"];
+        assert_eq!(bps[0]["verified"], true);
+        assert_eq!(bps[1]["verified"], false);
+
+        assert_eq!(
+            response(&msgs, "threads")["body"]["threads"][0]["id"],
+            THREAD_ID
+        );
+        assert_eq!(
+            response(&msgs, "stackTrace")["body"]["stackFrames"][0]["line"],
+            4
+        );
+        let vars = &response(&msgs, "variables")["body"]["variables"];
+        assert_eq!(vars[0]["name"], "b");
+        assert_eq!(vars[0]["value"], "2");
+        assert_eq!(response(&msgs, "evaluate")["body"]["result"], "20");
+
+        assert_eq!(
+            events(&msgs),
+            [
+                "initialized",
+                "stopped:breakpoint",
+                "output:2",
+                "output:1",
+                "exited",
+                "terminated"
+            ]
+        );
+    }
+
+    #[test]
+    fn stepping_and_errors() {
+        let msgs = session(
+            "stepping",
+            "var a = 1;\nprint a;\nprint a + nil;\n",
+            &[
+                ("initialize", json!({})),
+                ("launch", json!({"stopOnEntry": true})),
+                ("configurationDone", json!({})),
+                ("next", json!({"threadId": 1})),
+                ("stepIn", json!({"threadId": 1})),
+                ("variables", json!({"variablesReference": GLOBALS_REF})),
+                ("stepOut", json!({"threadId": 1})),
+            ],
+        );
+        assert_eq!(
+            events(&msgs),
+            [
+                "initialized",
+                "stopped:entry",
+                "stopped:step",
+                "output:1",
+                "stopped:step",
+                "output:runtime error: Op ADD not allowed on types \"Float(1.0)\" and \"Nil\"",
+                "exited",
+                "terminated"
+            ]
+        );
+        let vars = &response(&msgs, "variables")["body"]["variables"];
+        assert_eq!(vars[0]["name"], "a");
+        let exited = msgs.iter().find(|m| m["event"] == "exited").unwrap();
+        assert_eq!(exited["body"]["exitCode"], EXIT_RUNTIME);
+    }
+}
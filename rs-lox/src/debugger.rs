@@ -0,0 +1,413 @@
+//! `rs-lox debug file.lox` interactive step debugger.
+//!
+//! The program runs on a [`VM`] with a [`Hook`] that stops on breakpoints and after steps.
+//! Locals are found through the debug info the code generator leaves in the chunk,
+//! expressions typed at the prompt are compiled against them and run on the paused VM.
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use compiler::{CodeGen, Parser};
+use lang::Scanner;
+use values::{Chunk, Value};
+
+use crate::cli::{EXIT_COMPILE, EXIT_IO, EXIT_USAGE};
+use crate::errors::{COMPError, RTError};
+use crate::runtime::{Captured, Control, Exit, Hook, Output, VM};
+
+const HELP: &str = "\
+break N | b N      stop when line N is reached
+delete N           remove the breakpoint on line N
+breakpoints        list the breakpoints
+continue | c       run until the next breakpoint or the end
+step | s           run to the next line, stepping into calls
+next | n           run to the next line, stepping over calls
+out | o            run until the current call returns
+locals             print the local variables
+globals            print the global variables
+print EXPR | p     evaluate an expression where the program stopped
+list | l           show the source around the current line
+quit | q           leave the debugger";
+
+pub fn run(args: &[String]) -> i32 {
+    let Some(path) = args.first() else {
+        eprintln!("usage: rs-lox debug <file.lox>");
+        return EXIT_USAGE;
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return EXIT_IO;
+        }
+    };
+    let mut dbg = match Debugger::load(&source, io::stdout()) {
+        Ok(dbg) => dbg,
+        Err(e) => {
+            eprintln!(" Error: [\n\t {} \n]", e);
+            return EXIT_COMPILE;
+        }
+    };
+    let stdin = io::stdin();
+    match dbg.session(&mut stdin.lock()) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("debug: {}", e);
+            EXIT_IO
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Continue,
+    Step,
+    /// stop on the next line that is not in a deeper call
+    Over(usize),
+    /// stop once we are back in the caller
+    Out(usize),
+}
+
+/// Decides where the program stops
+struct Stops {
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    /// last op we looked at (ip, line), to notice when a new line starts
+    prev: Option<(usize, usize)>,
+    /// why we stopped last, for the report
+    hit_breakpoint: bool,
+}
+
+impl Hook for Stops {
+    fn before_op(&mut self, vm: &VM) -> Control {
+        let (ip, line) = (vm.ip(), vm.line());
+        // a new line starts when the line changes, or when a loop jumps back to its start.
+        // after a pause we see the same op again, that one doesn't count
+        let new_line = match self.prev {
+            None => true,
+            Some((prev_ip, prev_line)) => prev_line != line || ip < prev_ip,
+        };
+        self.prev = Some((ip, line));
+        if !new_line {
+            return Control::Continue;
+        }
+
+        self.hit_breakpoint = self.breakpoints.contains(&line);
+        let depth = vm.frame_depth();
+        let stop = self.hit_breakpoint
+            || match self.mode {
+                Mode::Continue => false,
+                Mode::Step => true,
+                Mode::Over(d) => depth <= d,
+                Mode::Out(d) => depth < d,
+            };
+        if stop {
+            Control::Pause
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+/// A program under the debugger, shared by the command line debugger and the DAP server
+pub struct Target {
+    vm: VM,
+    stops: Stops,
+    finished: bool,
+    /// what the program printed, when it's not written to stdout
+    captured: Option<Captured>,
+}
+
+impl Target {
+    pub fn load(source: &str) -> COMPError<Self> {
+        let mut scanner = Scanner::from_str(source)?;
+        let program = Parser::init(&mut scanner).parse()?;
+        let mut chunk = Chunk::new();
+        CodeGen::init(&mut chunk).generate(&program)?;
+
+        let mut vm = VM::init(false, Output::stdio());
+        vm.load_chunk(chunk);
+        Ok(Self {
+            vm,
+            stops: Stops {
+                breakpoints: BTreeSet::new(),
+                mode: Mode::Continue,
+                prev: None,
+                hit_breakpoint: false,
+            },
+            finished: false,
+            captured: None,
+        })
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Did the last pause happen on a breakpoint
+    pub fn hit_breakpoint(&self) -> bool {
+        self.stops.hit_breakpoint
+    }
+
+    /// Keep printed lines for [`Target::take_output`] instead of writing them to stdout
+    pub fn capture_output(&mut self) {
+        let out = Captured::default();
+        self.vm.set_output(Output::new(out.clone(), io::stderr()));
+        self.captured = Some(out);
+    }
+
+    pub fn take_output(&mut self) -> Vec<String> {
+        self.captured
+            .as_ref()
+            .map_or(vec![], |out| out.take().lines().map(String::from).collect())
+    }
+
+    pub fn has_code(&self, line: usize) -> bool {
+        self.vm.chunk().is_some_and(|c| {
+            c.source_map
+                .runs()
+                .any(|(_, pos)| pos.line as usize == line)
+        })
+    }
+
+    /// Returns false if there is no code on the line
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        if !self.has_code(line) {
+            return false;
+        }
+        self.stops.breakpoints.insert(line);
+        true
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.stops.breakpoints.remove(&line)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.stops.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.stops.breakpoints.iter()
+    }
+
+    pub fn resume(&mut self, mode: Mode) -> RTError<Exit> {
+        self.stops.mode = mode;
+        let res = self.vm.run_hooked(Some(&mut self.stops));
+        if !matches!(res, Ok(Exit::Paused)) {
+            self.finished = true;
+        }
+        res
+    }
+
+    /// Globals sorted by name
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals: Vec<_> = self
+            .vm
+            .globals()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    /// Evaluate an expression where the program stopped, it sees the live locals and the globals
+    pub fn eval(&mut self, source: &str) -> Result<Value, String> {
+        let mut scanner = Scanner::from_str(source).map_err(|e| e.to_string())?;
+        let expr = Parser::init(&mut scanner)
+            .parse_expression()
+            .map_err(|e| e.to_string())?;
+
+        let locals = self.vm.locals();
+        let names: Vec<&str> = locals.iter().map(|(n, _)| n.as_str()).collect();
+        let mut chunk = Chunk::new();
+        CodeGen::with_locals(&mut chunk, &names)
+            .generate_expr(&expr)
+            .map_err(|e| e.to_string())?;
+        self.vm.eval(chunk).map_err(|e| e.to_string())
+    }
+}
+
+pub struct Debugger<W: Write> {
+    target: Target,
+    lines: Vec<String>,
+    out: W,
+}
+
+impl<W: Write> Debugger<W> {
+    pub fn load(source: &str, out: W) -> COMPError<Self> {
+        Ok(Self {
+            target: Target::load(source)?,
+            lines: source.lines().map(|l| l.to_string()).collect(),
+            out,
+        })
+    }
+
+    /// Read commands until `quit` or the end of the input
+    pub fn session(&mut self, input: &mut impl BufRead) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "loaded {} lines, type `help` for the commands",
+            self.lines.len()
+        )?;
+        loop {
+            write!(self.out, "(lox-dbg) ")?;
+            self.out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim();
+            let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let arg = arg.trim();
+            let depth = self.target.vm().frame_depth();
+            match cmd {
+                "" => {}
+                "break" | "b" => self.set_breakpoint(arg)?,
+                "delete" => match arg.parse::<usize>() {
+                    Ok(n) if self.target.remove_breakpoint(n) => {
+                        writeln!(self.out, "removed breakpoint on line {}", n)?
+                    }
+                    _ => writeln!(self.out, "no breakpoint on line {}", arg)?,
+                },
+                "breakpoints" => {
+                    for n in self.target.breakpoints() {
+                        writeln!(self.out, "line {}", n)?;
+                    }
+                }
+                "continue" | "c" => self.resume(Mode::Continue)?,
+                "step" | "s" => self.resume(Mode::Step)?,
+                "next" | "n" => self.resume(Mode::Over(depth))?,
+                "out" | "o" => self.resume(Mode::Out(depth))?,
+                "locals" => {
+                    for (name, val) in self.target.vm().locals() {
+                        writeln!(self.out, "{} = {}", name, val)?;
+                    }
+                }
+                "globals" => {
+                    for (name, val) in self.target.globals() {
+                        writeln!(self.out, "{} = {}", name, val)?;
+                    }
+                }
+                "print" | "p" => match self.target.eval(arg) {
+                    Ok(val) => writeln!(self.out, "{}", val)?,
+                    Err(e) => writeln!(self.out, "{}", e)?,
+                },
+                "list" | "l" => self.list()?,
+                "help" | "h" => writeln!(self.out, "{}", HELP)?,
+                "quit" | "q" => return Ok(()),
+                _ => writeln!(self.out, "unknown command `{}`, try `help`", cmd)?,
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, arg: &str) -> io::Result<()> {
+        match arg.parse::<usize>() {
+            Ok(n) if self.target.add_breakpoint(n) => {
+                writeln!(self.out, "breakpoint on line {}", n)
+            }
+            Ok(n) => writeln!(self.out, "no code on line {}", n),
+            Err(_) => writeln!(self.out, "expected a line number, got `{}`", arg),
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) -> io::Result<()> {
+        if self.target.finished() {
+            return writeln!(self.out, "the program is not running");
+        }
+        match self.target.resume(mode) {
+            Ok(Exit::Paused) => {
+                let line = self.target.vm().line();
+                if self.target.hit_breakpoint() {
+                    writeln!(self.out, "breakpoint, line {}", line)?;
+                }
+                self.show_line(line)
+            }
+            Ok(Exit::Done) => writeln!(self.out, "program finished"),
+            Err(e) => writeln!(self.out, "runtime error: {}", e),
+        }
+    }
+
+    fn list(&mut self) -> io::Result<()> {
+        let cur = self.target.vm().line();
+        let from = cur.saturating_sub(3).max(1);
+        for n in from..=(cur + 3).min(self.lines.len()) {
+            let marker = if n == cur && !self.target.finished() {
+                ">"
+            } else {
+                " "
+            };
+            writeln!(self.out, "{} {:>3} | {}", marker, n, self.lines[n - 1])?;
+        }
+        Ok(())
+    }
+
+    fn show_line(&mut self, line: usize) -> io::Result<()> {
+        let text = self
+            .lines
+            .get(line.wrapping_sub(1))
+            .map_or("", |l| l.trim());
+        writeln!(self.out, "> {:>3} | {}", line, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PROGRAM: &str = "var total = 0;
+for (var i = 0; i < 3; i = i + 1) {
+    var sq = i * i;
+    total = total + sq;
+}
+print total;
+";
+
+    fn session(commands: &str) -> String {
+        let mut dbg = Debugger::load(PROGRAM, vec![]).unwrap();
+        dbg.session(&mut Cursor::new(commands)).unwrap();
+        String::from_utf8(dbg.out).unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_locals() {
+        let out = session("b 4\nb 5\nc\nlocals\nc\nlocals\np sq * 10 + total\np nope\nglobals\n");
+        assert!(out.contains("no code on line 5"), "{}", out);
+        assert!(out.contains("breakpoint, line 4\n>   4 | total = total + sq;"));
+        assert!(out.contains("i = 0\nsq = 0\n"));
+        // second time around the loop
+        assert!(out.contains("i = 1\nsq = 1\n"));
+        assert!(out.contains("(lox-dbg) 10\n"));
+        assert!(out.contains("Unknown variable nope"));
+        assert!(out.contains("total = 0\n"));
+    }
+
+    #[test]
+    fn stepping() {
+        let out = session("s\ns\ns\nn\np i = 7\nlocals\nc\nc\n");
+        let stops: Vec<&str> = out
+            .lines()
+            .filter_map(|l| l.split("> ").nth(1))
+            .filter(|l| l.contains('|'))
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                "  1 | var total = 0;",
+                "  2 | for (var i = 0; i < 3; i = i + 1) {",
+                "  3 | var sq = i * i;",
+                "  4 | total = total + sq;",
+            ]
+        );
+        // assignments from the prompt change the program state
+        assert!(out.contains("i = 7\nsq = 0\n"));
+        assert!(out.contains("program finished\n"));
+        assert!(out.contains("the program is not running"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Human readable listing of the bytecode in a chunk.
+use lang::OpCode;
+use values::{Chunk, Value};
+
+/// One op per line: address, source line (`|` while it stays the same) and the op.
+/// Ops that use a constant show it too
+pub fn disassemble(chunk: &Chunk) -> String {
+    let mut out = String::new();
+    let mut last_line = None;
+    for (ip, op) in chunk.instructions() {
+        let line = chunk.get_line_num(ip);
+        let line_txt = match last_line.replace(line) {
+            Some(last) if last == line => "|".to_string(),
+            _ => line.to_string(),
+        };
+        let op_txt = format!("{:?}", op);
+        let listed = match constant(chunk, op) {
+            Some(val) => format!("{:04} {:>4} {:<18} {}", ip, line_txt, op_txt, val),
+            None => format!("{:04} {:>4} {}", ip, line_txt, op_txt),
+        };
+        out.push_str(&listed);
+        out.push('\n');
+    }
+    out
+}
+
+/// The constant an op refers to, strings are quoted so names stand out
+fn constant(chunk: &Chunk, op: OpCode) -> Option<String> {
+    use OpCode::*;
+    let (CONSTANT(idx) | DEFINE_GLOBAL(idx) | GET_GLOBAL(idx) | SET_GLOBAL(idx) | INVOKE(idx, _)) =
+        op
+    else {
+        return None;
+    };
+    Some(match chunk.consts.get(idx as usize) {
+        Some(Value::String(s)) => format!("{:?}", s),
+        Some(val) => val.to_string(),
+        None => "???".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::RuntimeContext;
+
+    #[test]
+    fn listing() {
+        let mut runtime = RuntimeContext::start(false);
+        let addr = runtime.compile("var a = \"x\";\nprint a + 1;").unwrap();
+        let listing = disassemble(&runtime.get_chunk(addr));
+        assert_eq!(
+            listing,
+            "\
+0000    1 CONSTANT(0)        \"x\"
+0002    | DEFINE_GLOBAL(1)   \"a\"
+0004    2 GET_GLOBAL(2)      \"a\"
+0006    | CONSTANT(3)        1
+0008    | ADD
+0009    | PRINT
+0010    | RETURN
+"
+        );
+    }
+}
@@ -4,8 +4,8 @@ pub type RTError<T> = Result<T, RuntimeError>;
 use thiserror::Error;
 
 pub use lang::CompileError;
-use lang::OpCode;
-use values::Value;
+use lang::{cite_span, offset_of, OpCode};
+use values::{NativeError, SourcePos, Value, VerifyError};
 
 #[derive(Debug, Error)]
 pub enum RuntimeError {
@@ -15,6 +15,53 @@ pub enum RuntimeError {
     IllegalUnaryOp(OpCode, Value),
     #[error("Op {0:?} not allowed on types {1:?} and {2:?}")]
     IllegalOp(OpCode, String, String),
+    #[error("Invalid bytecode: {0}")]
+    InvalidChunk(#[from] VerifyError),
     #[error("Unknown variable {0}")]
     UnknownVariable(String),
+    #[error("Local slot {0} is not on the stack")]
+    BadLocal(u8),
+    #[error("Condition must be a bool, got {0:?}")]
+    NotABool(Value),
+    #[error("line {0}: assertion failed, got {1}")]
+    AssertFailed(usize, Value),
+    #[error("line {0}: assert_eq failed\n  left: {1}\n right: {2}")]
+    AssertEqFailed(usize, Value, Value),
+    #[error("Can only call functions, got {0:?}")]
+    NotCallable(Value),
+    #[error("{0:?} has no method {1}()")]
+    NoMethod(Value, String),
+    #[error("{0}() takes {1} arguments but got {2}")]
+    Arity(&'static str, u8, usize),
+    #[error("{0}(): {1}")]
+    Native(&'static str, NativeError),
+    #[error("Can't index {0:?} with {1:?}")]
+    BadIndex(Value, Value),
+    #[error("Index {0} is out of range, the length is {1}")]
+    IndexOutOfRange(usize, usize),
+    #[error("Can't write the output: {0}")]
+    Output(#[from] std::io::Error),
+    /// not a failure, the program called `exit(code)`
+    #[error("exit({0})")]
+    Exit(i32),
+}
+
+/// A runtime error with the position of the op that failed.
+/// With the source at hand the failing expression is cited as well
+pub fn format_runtime_error(
+    e: &RuntimeError,
+    pos: Option<SourcePos>,
+    source: Option<&str>,
+) -> String {
+    let mut out = format!(" Error: [ \n\t {} \n ]\n", e);
+    let Some(pos) = pos else {
+        return out;
+    };
+    out.push_str(&format!(" at {}\n", pos));
+    // hand made chunks only know the line
+    if let Some(source) = source.filter(|_| pos.col > 0) {
+        let start = offset_of(source, pos.line, pos.col);
+        out.push_str(&cite_span(source, start, start + pos.len as usize));
+    }
+    out
 }
@@ -0,0 +1,57 @@
+//! `rs-lox fmt [--check] [paths..]` rewrites files in the canonical style.
+//! With `--check` nothing is written, we only report files that would change.
+use std::fs;
+
+use compiler::format::format;
+
+use crate::cli::{EXIT_COMPILE, EXIT_FAILURE, EXIT_IO};
+use crate::sources::collect_lox_files;
+
+pub fn run(args: &[String]) -> i32 {
+    let check = args.iter().any(|a| a == "--check");
+    let mut paths: Vec<String> = args.iter().filter(|a| *a != "--check").cloned().collect();
+    if paths.is_empty() {
+        paths.push(".".to_string());
+    }
+
+    let files = match collect_lox_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("fmt: {}", e);
+            return EXIT_IO;
+        }
+    };
+
+    let mut code = 0;
+    for path in files {
+        let source = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("fmt: {}: {}", path.display(), e);
+                code = code.max(EXIT_IO);
+                continue;
+            }
+        };
+        let formatted = match format(&source) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("fmt: {}: {}", path.display(), e);
+                code = code.max(EXIT_COMPILE);
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("would reformat {}", path.display());
+            code = code.max(EXIT_FAILURE);
+        } else if let Err(e) = fs::write(&path, formatted) {
+            eprintln!("fmt: {}: {}", path.display(), e);
+            code = code.max(EXIT_IO);
+        } else {
+            println!("formatted {}", path.display());
+        }
+    }
+    code
+}
@@ -0,0 +1,111 @@
+//! Terminal colors for lox source, driven by the lossless [`Scanner`].
+//!
+//! Scan errors, like a string that is still open, are underlined in red so they show up before
+//! the input is submitted.
+use lang::{Scanner, TokenType};
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const IDENT: &str = "\x1b[36m";
+const COMMENT: &str = "\x1b[90m";
+const ERROR: &str = "\x1b[4;31m";
+
+/// `source` with color codes around every token, `None` if it can't be scanned at all
+pub fn highlight(source: &str) -> Option<String> {
+    let mut scanner = Scanner::lossless(source).ok()?;
+    let mut out = String::new();
+    loop {
+        match scanner.scan_token() {
+            Ok(tok) if tok.ty == TokenType::EoF => break,
+            Ok(tok) => paint(&mut out, color(tok.ty), scanner.lexeme()),
+            Err(_) => {
+                // the scanner skips the char it got stuck on, an open string runs to the end
+                let end = scanner.cur_pos.max(scanner.start_pos + 1).min(source.len());
+                paint(&mut out, Some(ERROR), &source[scanner.start_pos..end]);
+                if end == source.len() {
+                    break;
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+fn color(ty: TokenType) -> Option<&'static str> {
+    match ty {
+        TokenType::String => Some(STRING),
+        TokenType::Number => Some(NUMBER),
+        TokenType::Ident => Some(IDENT),
+        TokenType::Comment => Some(COMMENT),
+        ty if ty.is_keyword() => Some(KEYWORD),
+        _ => None,
+    }
+}
+
+fn paint(out: &mut String, color: Option<&str>, text: &str) {
+    match color {
+        Some(color) => {
+            out.push_str(color);
+            out.push_str(text);
+            out.push_str(RESET);
+        }
+        None => out.push_str(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(colored: &str) -> String {
+        let mut out = String::new();
+        let mut chars = colored.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\x1b' {
+                chars.by_ref().find(|ch| *ch == 'm');
+            } else {
+                out.push(ch);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn tokens_are_colored() {
+        let colored = highlight("var a = 1.5; // one\nprint \"x\" + a;").unwrap();
+        assert!(colored.starts_with("\x1b[35mvar\x1b[0m \x1b[36ma\x1b[0m = \x1b[33m1.5\x1b[0m;"));
+        assert!(colored.contains("\x1b[90m// one\x1b[0m"));
+        assert!(colored.contains("\x1b[32m\"x\"\x1b[0m"));
+    }
+
+    #[test]
+    fn scan_errors_are_underlined() {
+        let colored = highlight("print \"abc + 1").unwrap();
+        assert!(
+            colored.ends_with("\x1b[4;31m\"abc + 1\x1b[0m"),
+            "{:?}",
+            colored
+        );
+        let colored = highlight("a @ b").unwrap();
+        assert!(
+            colored.contains("\x1b[4;31m@\x1b[0m \x1b[36mb"),
+            "{:?}",
+            colored
+        );
+    }
+
+    #[test]
+    fn text_is_unchanged() {
+        for source in [
+            "if (a >= 2) {\n  print a; // x\n}",
+            "print \"open",
+            "#$ @ 1",
+            "",
+        ] {
+            assert_eq!(strip(&highlight(source).unwrap()), source);
+        }
+        assert_eq!(highlight("print \"é\";"), None);
+    }
+}
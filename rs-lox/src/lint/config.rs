@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lang::{Scanner, TokenType};
+
+use super::{Level, Rule};
+
+/// Name of the project config, looked up from the linted file's directory upwards
+pub const CONFIG_FILE: &str = ".loxlint";
+
+const DIRECTIVE: &str = "lox-lint:";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintConfig {
+    levels: HashMap<Rule, Level>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        let levels = Rule::ALL.iter().map(|r| (*r, r.default_level())).collect();
+        Self { levels }
+    }
+}
+
+impl LintConfig {
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels[&rule]
+    }
+
+    pub fn set(&mut self, rule: Rule, level: Level) {
+        self.levels.insert(rule, level);
+    }
+
+    /// Config lines look like `empty-block = allow`, `#` starts a comment
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, level) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `rule = level`", idx + 1))?;
+            let rule = Rule::from_name(name.trim())
+                .ok_or_else(|| format!("line {}: unknown rule `{}`", idx + 1, name.trim()))?;
+            let level = Level::from_name(level.trim())
+                .ok_or_else(|| format!("line {}: unknown level `{}`", idx + 1, level.trim()))?;
+            config.set(rule, level);
+        }
+        Ok(config)
+    }
+
+    /// Closest config file to `path`, defaults if there is none
+    pub fn discover(path: &Path) -> Result<(Self, Option<PathBuf>), String> {
+        let start = path.parent().unwrap_or(path);
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        for dir in start.ancestors() {
+            let candidate = dir.join(CONFIG_FILE);
+            if candidate.is_file() {
+                let text = fs::read_to_string(&candidate).map_err(|e| e.to_string())?;
+                let config =
+                    Self::parse(&text).map_err(|e| format!("{}: {}", candidate.display(), e))?;
+                return Ok((config, Some(candidate)));
+            }
+        }
+        Ok((Self::default(), None))
+    }
+
+    /// Apply `// lox-lint: allow(rule, ..)` style comments of a file (`warn` and `deny` work too).
+    /// Returns names we didn't recognize
+    pub fn apply_directives(&mut self, source: &str) -> Vec<String> {
+        let mut unknown = vec![];
+        let mut scanner = match Scanner::lossless(source) {
+            Ok(s) => s,
+            Err(_) => return unknown,
+        };
+        loop {
+            match scanner.scan_token() {
+                Ok(tok) if tok.ty == TokenType::EoF => break,
+                Ok(tok) if tok.ty == TokenType::Comment => {
+                    let text = scanner.lexeme().trim_start_matches('/').trim();
+                    if let Some(directive) = text.strip_prefix(DIRECTIVE) {
+                        self.directive(directive.trim(), &mut unknown);
+                    }
+                }
+                Ok(_) => {}
+                // scan errors are reported by the parser
+                Err(_) => {}
+            }
+        }
+        unknown
+    }
+
+    fn directive(&mut self, text: &str, unknown: &mut Vec<String>) {
+        let Some((level, rest)) = text.split_once('(') else {
+            unknown.push(text.to_string());
+            return;
+        };
+        let Some(level) = Level::from_name(level.trim()) else {
+            unknown.push(level.trim().to_string());
+            return;
+        };
+        let names = rest.trim_end().trim_end_matches(')');
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match Rule::from_name(name) {
+                Some(rule) => self.set(rule, level),
+                None => unknown.push(name.to_string()),
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! `rs-lox lint [paths..]` static checks for code that compiles but is most likely wrong.
+//!
+//! Every rule has a level (`allow`, `warn` or `deny`). Defaults can be changed per project
+//! with a `.loxlint` file and per file with `// lox-lint: allow(rule)` comments.
+//! The command exits with 1 if anything at `deny` level was found, 65 if a file doesn't compile,
+//! 74 if a file can't be read and 64 if a `.loxlint` file is broken.
+mod config;
+mod rules;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use compiler::ast::Span;
+use compiler::Parser;
+use lang::{cite_span, line_col, CompileError, Scanner};
+
+use crate::cli::{EXIT_COMPILE, EXIT_FAILURE, EXIT_IO, EXIT_USAGE};
+use crate::sources::collect_lox_files;
+
+pub use config::LintConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    MixedTypeComparison,
+    GlobalRedeclaration,
+    EmptyBlock,
+    ConstantCondition,
+    SelfAssignment,
+    NonBooleanLogic,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::MixedTypeComparison,
+        Rule::GlobalRedeclaration,
+        Rule::EmptyBlock,
+        Rule::ConstantCondition,
+        Rule::SelfAssignment,
+        Rule::NonBooleanLogic,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::MixedTypeComparison => "mixed-type-comparison",
+            Rule::GlobalRedeclaration => "global-redeclaration",
+            Rule::EmptyBlock => "empty-block",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::SelfAssignment => "self-assignment",
+            Rule::NonBooleanLogic => "non-boolean-logic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|r| r.name() == name)
+    }
+
+    /// Things that blow up at runtime are denied, the rest is just suspicious
+    pub fn default_level(&self) -> Level {
+        match self {
+            Rule::MixedTypeComparison | Rule::NonBooleanLogic => Level::Deny,
+            _ => Level::Warn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warning"),
+            Level::Deny => write!(f, "error"),
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub level: Level,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn render(&self, path: &Path, source: &str) -> String {
+        let (line, col) = line_col(source, self.span.start);
+        format!(
+            "{}[{}]: {}\n --> {}:{}:{}\n{}",
+            self.level,
+            self.rule.name(),
+            self.message,
+            path.display(),
+            line,
+            col,
+            cite_span(source, self.span.start, self.span.end)
+        )
+    }
+}
+
+/// Lint a single source. `config` should already have the file's directives applied
+pub fn lint_source(source: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, CompileError> {
+    let mut scanner = Scanner::from_str(source)?;
+    let program = Parser::init(&mut scanner).parse()?;
+    let diagnostics = rules::Checker::default()
+        .check(&program)
+        .into_iter()
+        .filter_map(|f| {
+            let level = config.level(f.rule);
+            (level != Level::Allow).then_some(Diagnostic {
+                rule: f.rule,
+                level,
+                span: f.span,
+                message: f.message,
+            })
+        })
+        .collect();
+    Ok(diagnostics)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let mut paths = args.to_vec();
+    if paths.is_empty() {
+        paths.push(".".to_string());
+    }
+    let files = match collect_lox_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("lint: {}", e);
+            return EXIT_IO;
+        }
+    };
+
+    let mut code = 0;
+    for path in files {
+        let source = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("lint: {}: {}", path.display(), e);
+                code = code.max(EXIT_IO);
+                continue;
+            }
+        };
+        let mut config = match LintConfig::discover(&path) {
+            Ok((config, _)) => config,
+            Err(e) => {
+                // a broken .loxlint is a mistake in how we were asked to run
+                eprintln!("lint: {}", e);
+                return EXIT_USAGE;
+            }
+        };
+        for name in config.apply_directives(&source) {
+            eprintln!(
+                "lint: {}: unknown lint directive `{}`",
+                path.display(),
+                name
+            );
+        }
+
+        match lint_source(&source, &config) {
+            Ok(diagnostics) => {
+                for d in diagnostics {
+                    println!("{}", d.render(&path, &source));
+                    if d.level == Level::Deny {
+                        code = code.max(EXIT_FAILURE);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("lint: {}: {}", path.display(), e);
+                code = code.max(EXIT_COMPILE);
+            }
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules_hit(source: &str) -> Vec<&'static str> {
+        let mut config = LintConfig::default();
+        config.apply_directives(source);
+        lint_source(source, &config)
+            .unwrap()
+            .iter()
+            .map(|d| d.rule.name())
+            .collect()
+    }
+
+    #[test]
+    fn every_rule_fires() {
+        let source = "var a = 1;\nvar a = 2;\nprint 1 == \"one\";\n{}\nif (true) print a;\na = (a);\nprint a and 3;";
+        assert_eq!(
+            rules_hit(source),
+            vec![
+                "global-redeclaration",
+                "mixed-type-comparison",
+                "empty-block",
+                "constant-condition",
+                "self-assignment",
+                "non-boolean-logic"
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_code_passes() {
+        let source = "var a = 1;\n{ var a = 2; print a > 1 and true; }\nfor (var i = 0; i < 2; i = i + 1) print i;";
+        assert!(rules_hit(source).is_empty());
+    }
+
+    #[test]
+    fn file_directives() {
+        let source = "// lox-lint: allow(empty-block, self-assignment)\nvar a;\n{}\na = a;";
+        assert!(rules_hit(source).is_empty());
+
+        let mut config = LintConfig::default();
+        let unknown = config.apply_directives("// lox-lint: allow(no-such-rule)");
+        assert_eq!(unknown, vec!["no-such-rule".to_string()]);
+    }
+
+    #[test]
+    fn config_file() {
+        let config =
+            LintConfig::parse("# project\nempty-block = deny\nself-assignment=allow\n").unwrap();
+        assert_eq!(config.level(Rule::EmptyBlock), Level::Deny);
+        assert_eq!(config.level(Rule::SelfAssignment), Level::Allow);
+        assert_eq!(config.level(Rule::ConstantCondition), Level::Warn);
+        assert!(LintConfig::parse("empty-block = maybe").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use compiler::ast::{BinaryOp, Expr, ExprKind, Program, Span, Stmt, StmtKind, UnaryOp};
+use std::collections::HashSet;
+
+use super::Rule;
+
+pub struct Finding {
+    pub rule: Rule,
+    pub span: Span,
+    pub message: String,
+}
+
+/// Type we can tell from the code alone, without running it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StaticTy {
+    Number,
+    Str,
+    Bool,
+    Nil,
+}
+
+impl StaticTy {
+    fn name(&self) -> &'static str {
+        match self {
+            StaticTy::Number => "number",
+            StaticTy::Str => "string",
+            StaticTy::Bool => "bool",
+            StaticTy::Nil => "nil",
+        }
+    }
+}
+
+fn static_ty(expr: &Expr) -> Option<StaticTy> {
+    use StaticTy::*;
+    match &expr.kind {
+        ExprKind::Number(_) => Some(Number),
+        ExprKind::Str(_) => Some(Str),
+        ExprKind::Bool(_) => Some(Bool),
+        ExprKind::Nil => Some(Nil),
+        ExprKind::Grouping(e) => static_ty(e),
+        ExprKind::Unary {
+            op: UnaryOp::Not, ..
+        } => Some(Bool),
+        ExprKind::Unary {
+            op: UnaryOp::Neg,
+            rhs,
+        } => static_ty(rhs).filter(|t| *t == Number),
+        ExprKind::Binary { op, lhs, rhs } => match op {
+            BinaryOp::Add => match (static_ty(lhs), static_ty(rhs)) {
+                (Some(Str), _) | (_, Some(Str)) => Some(Str),
+                (Some(Number), Some(Number)) => Some(Number),
+                _ => None,
+            },
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => Some(Number),
+            _ => Some(Bool),
+        },
+        ExprKind::Logical { .. } => Some(Bool),
+        ExprKind::Variable(_)
+        | ExprKind::Assign { .. }
+        | ExprKind::Call { .. }
+        | ExprKind::Index { .. }
+        | ExprKind::Invoke { .. } => None,
+    }
+}
+
+fn is_constant(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Str(_) | ExprKind::Bool(_) | ExprKind::Nil => true,
+        ExprKind::Grouping(e) | ExprKind::Unary { rhs: e, .. } => is_constant(e),
+        ExprKind::Binary { lhs, rhs, .. } | ExprKind::Logical { lhs, rhs, .. } => {
+            is_constant(lhs) && is_constant(rhs)
+        }
+        ExprKind::Variable(_)
+        | ExprKind::Assign { .. }
+        | ExprKind::Call { .. }
+        | ExprKind::Index { .. }
+        | ExprKind::Invoke { .. } => false,
+    }
+}
+
+fn unparen(expr: &Expr) -> &Expr {
+    match &expr.kind {
+        ExprKind::Grouping(e) => unparen(e),
+        _ => expr,
+    }
+}
+
+/// Walks the tree once and collects findings of every rule, filtering happens later
+#[derive(Default)]
+pub struct Checker {
+    findings: Vec<Finding>,
+    globals: HashSet<String>,
+    depth: usize,
+}
+
+impl Checker {
+    pub fn check(mut self, program: &Program) -> Vec<Finding> {
+        for stmt in program.decls.iter() {
+            self.stmt(stmt);
+        }
+        self.findings
+    }
+
+    fn report(&mut self, rule: Rule, span: Span, message: String) {
+        self.findings.push(Finding {
+            rule,
+            span,
+            message,
+        });
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Var { name, init } => {
+                if let Some(e) = init {
+                    self.expr(e);
+                }
+                if self.depth == 0 && !self.globals.insert(name.name.clone()) {
+                    self.report(
+                        Rule::GlobalRedeclaration,
+                        name.span,
+                        format!("global `{}` is already declared", name.name),
+                    );
+                }
+            }
+            StmtKind::Print(e) | StmtKind::Expression(e) | StmtKind::Assert(e) => self.expr(e),
+            StmtKind::AssertEq(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            StmtKind::Test { body, .. } => self.stmt(body),
+            StmtKind::Block(stmts) => {
+                if stmts.is_empty() {
+                    self.report(Rule::EmptyBlock, stmt.span, "empty block".to_string());
+                }
+                self.depth += 1;
+                for s in stmts {
+                    self.stmt(s);
+                }
+                self.depth -= 1;
+            }
+            StmtKind::If { cond, then_, else_ } => {
+                if is_constant(cond) {
+                    self.report(
+                        Rule::ConstantCondition,
+                        cond.span,
+                        "`if` condition is a constant, one of the branches never runs".to_string(),
+                    );
+                }
+                self.expr(cond);
+                self.stmt(then_);
+                if let Some(e) = else_ {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                incr,
+                body,
+            } => {
+                // the loop variable lives in its own scope
+                self.depth += 1;
+                if let Some(s) = init {
+                    self.stmt(s);
+                }
+                for e in [cond, incr].into_iter().flatten() {
+                    self.expr(e);
+                }
+                self.stmt(body);
+                self.depth -= 1;
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign { name, value } => {
+                if let ExprKind::Variable(v) = &unparen(value).kind {
+                    if v.name == name.name {
+                        self.report(
+                            Rule::SelfAssignment,
+                            expr.span,
+                            format!("`{}` is assigned to itself", name.name),
+                        );
+                    }
+                }
+                self.expr(value);
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let comparison = !matches!(
+                    op,
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+                );
+                if comparison {
+                    if let (Some(l), Some(r)) = (static_ty(lhs), static_ty(rhs)) {
+                        if l != r {
+                            self.report(
+                                Rule::MixedTypeComparison,
+                                expr.span,
+                                format!(
+                                    "comparing a {} with a {} is a runtime error",
+                                    l.name(),
+                                    r.name()
+                                ),
+                            );
+                        }
+                    }
+                }
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Logical { op, lhs, rhs } => {
+                for side in [lhs, rhs] {
+                    if let Some(ty @ (StaticTy::Number | StaticTy::Str)) = static_ty(side) {
+                        self.report(
+                            Rule::NonBooleanLogic,
+                            side.span,
+                            format!("`{}` on a {} operand, expected a bool", op, ty.name()),
+                        );
+                    }
+                }
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Grouping(e) | ExprKind::Unary { rhs: e, .. } => self.expr(e),
+            ExprKind::Call { callee, args }
+            | ExprKind::Invoke {
+                target: callee,
+                args,
+                ..
+            } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Number(_)
+            | ExprKind::Str(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Nil
+            | ExprKind::Variable(_) => {}
+        }
+    }
+}
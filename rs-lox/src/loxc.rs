@@ -0,0 +1,175 @@
+//! `rs-lox compile file.lox [-o file.loxc]` saves the bytecode, see [`values::loxc`] for the format.
+//! `rs-lox run` takes a compiled file just like a source file, the compiler is skipped.
+use std::fs;
+use std::path::Path;
+
+use values::loxc::is_compiled;
+use values::Chunk;
+
+use crate::cli::{EXIT_COMPILE, EXIT_IO, EXIT_USAGE};
+use crate::session::{ChunkAddr, RuntimeContext};
+
+const USAGE: &str = "usage: rs-lox compile <file.lox> [-o <file.loxc>]";
+
+pub fn run(args: &[String]) -> i32 {
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" if output.is_none() => match args.next() {
+                Some(out) => output = Some(out.clone()),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return EXIT_USAGE;
+                }
+            },
+            _ if input.is_none() && arg != "-o" => input = Some(arg.clone()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    };
+    let output = output.unwrap_or_else(|| {
+        Path::new(&input)
+            .with_extension("loxc")
+            .to_string_lossy()
+            .to_string()
+    });
+
+    let source = match fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}", input, e);
+            return EXIT_IO;
+        }
+    };
+    let mut runtime = RuntimeContext::start(false);
+    let chunk = match runtime.compile(&source) {
+        Ok(addr) => runtime.get_chunk(addr),
+        Err(e) => {
+            let _ = writeln!(runtime.output().diag(), " Error: [\n\t {} \n]", e);
+            return EXIT_COMPILE;
+        }
+    };
+    let bytes = match chunk.to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {}", input, e);
+            return EXIT_COMPILE;
+        }
+    };
+    if let Err(e) = fs::write(&output, bytes) {
+        eprintln!("{}: {}", output, e);
+        return EXIT_IO;
+    }
+    0
+}
+
+/// Compile a source file, or load a compiled one, into the runtime. `name` is only used in errors.
+/// Gives the source back, compiled files have none. The error was already reported, `Err` holds
+/// the exit code
+pub fn load(
+    runtime: &mut RuntimeContext,
+    name: &str,
+    bytes: Vec<u8>,
+) -> Result<(ChunkAddr, Option<String>), i32> {
+    if is_compiled(&bytes) {
+        return match Chunk::from_bytes(&bytes) {
+            Ok(chunk) => Ok((runtime.load(chunk), None)),
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                Err(EXIT_COMPILE)
+            }
+        };
+    }
+    let source = String::from_utf8(bytes).map_err(|_| {
+        eprintln!("{}: not a lox source file", name);
+        EXIT_COMPILE
+    })?;
+    match runtime.compile(&source) {
+        Ok(addr) => Ok((addr, Some(source))),
+        Err(e) => {
+            let _ = writeln!(runtime.output().diag(), " Error: [\n\t {} \n]", e);
+            Err(EXIT_COMPILE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Captured, Output};
+    use std::io;
+
+    #[test]
+    fn compile_and_run() {
+        let dir = std::env::temp_dir();
+        let source = dir.join("rs-lox-loxc.lox");
+        let compiled = dir.join("rs-lox-loxc.loxc");
+        fs::write(&source, "var a = \"x\";\n{ var b = 2; print a + b; }\n").unwrap();
+        let args = [
+            source.to_string_lossy().to_string(),
+            "-o".to_string(),
+            compiled.to_string_lossy().to_string(),
+        ];
+        assert_eq!(run(&args), 0);
+        // the source is gone, we only need the compiled file
+        fs::remove_file(&source).unwrap();
+
+        let out = Captured::default();
+        let mut runtime = RuntimeContext::with_output(false, Output::new(out.clone(), io::sink()));
+        let (addr, source) = load(&mut runtime, "x.loxc", fs::read(&compiled).unwrap()).unwrap();
+        assert_eq!(source, None);
+        runtime.exec(addr).unwrap();
+        assert_eq!(out.take(), "x2\n");
+
+        let mut bytes = fs::read(&compiled).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(load(&mut runtime, "x.loxc", bytes), Err(EXIT_COMPILE));
+        fs::remove_file(&compiled).unwrap();
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let run = |argv: &str| {
+            let args: Vec<String> = argv.split_whitespace().map(|a| a.to_string()).collect();
+            run(&args)
+        };
+        // nothing is read before the arguments are known to be good
+        for bad in [
+            "",
+            "a.lox -o",
+            "a.lox b.lox",
+            "-o a.loxc",
+            "a.lox -o x -o y",
+        ] {
+            assert_eq!(run(bad), EXIT_USAGE, "{}", bad);
+        }
+        assert_eq!(run("/no/such/file.lox -o x.loxc"), EXIT_IO);
+    }
+
+    #[test]
+    fn bad_bytecode_is_refused() {
+        let mut chunk = Chunk::new();
+        chunk.add_op(lang::OpCode::JUMP(7), 1);
+        chunk.add_op(lang::OpCode::RETURN, 1);
+        let path = std::env::temp_dir().join("rs-lox-bad.loxc");
+        fs::write(&path, chunk.to_bytes().unwrap()).unwrap();
+
+        let mut runtime = RuntimeContext::start(false);
+        let (addr, _) = load(&mut runtime, "bad.loxc", fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let err = runtime.exec(addr).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid bytecode: @0: jump to 7 is not the start of an op"
+        );
+    }
+}
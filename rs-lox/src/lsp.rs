@@ -0,0 +1,546 @@
+//! `rs-lox lsp` language server over stdio.
+//!
+//! Diagnostics come from the lossless parser (so we report every syntax error, not just the first)
+//! and from code generation. Completion, hover, go to definition and document symbols are answered
+//! from [`Analysis`]. Sources are ASCII, so LSP character offsets are plain byte offsets.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use compiler::analysis::{Analysis, Symbol, SymbolKind};
+use compiler::{cst, CodeGen, Parser};
+use lang::{CompileError, Scanner};
+use serde_json::{json, Value as Json};
+use values::Chunk;
+
+use crate::cli::EXIT_IO;
+use crate::wire::{read_message, write_message};
+
+pub(crate) const KEYWORDS: [&str; 19] = [
+    "and",
+    "assert",
+    "assert_eq",
+    "class",
+    "else",
+    "false",
+    "for",
+    "fun",
+    "if",
+    "nil",
+    "or",
+    "print",
+    "return",
+    "super",
+    "test",
+    "this",
+    "true",
+    "var",
+    "while",
+];
+
+// LSP enum values we use
+const SEVERITY_ERROR: u32 = 1;
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_VARIABLE: u32 = 6;
+const SYMBOL_VARIABLE: u32 = 13;
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+pub fn run() -> i32 {
+    let stdin = io::stdin();
+    let mut server = Server::new(io::stdout());
+    match server.serve(&mut stdin.lock()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("lsp: {}", e);
+            EXIT_IO
+        }
+    }
+}
+
+struct Document {
+    text: String,
+    /// analysis of the current text, `None` if it doesn't compile
+    analysis: Option<Analysis>,
+    /// last text that compiled and its analysis. completion uses it while the user is mid edit
+    last_good: Option<(String, Analysis)>,
+}
+
+pub struct Server<W: Write> {
+    out: W,
+    docs: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            docs: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Handle messages until `exit` or until the client closes the input.
+    /// Returns the process exit code
+    pub fn serve(&mut self, input: &mut impl BufRead) -> io::Result<i32> {
+        loop {
+            let msg = match read_message(input) {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                // the whole frame was read, only what is in it is bad. the next one is fine to read
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    self.reply_error(&Json::Null, PARSE_ERROR, e.to_string())?;
+                    continue;
+                }
+                // the input ended in the middle of a frame
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let id = msg.get("id");
+            let Some(method) = msg["method"].as_str() else {
+                let id = id.cloned().unwrap_or(Json::Null);
+                self.reply_error(&id, INVALID_REQUEST, "no method".to_string())?;
+                continue;
+            };
+            if method == "exit" {
+                return Ok(if self.shutdown { 0 } else { 1 });
+            }
+            match id {
+                Some(id) => match self.request(method, &msg["params"]) {
+                    Some(result) => {
+                        let reply = json!({"jsonrpc": "2.0", "id": id, "result": result});
+                        write_message(&mut self.out, &reply)?;
+                    }
+                    None => {
+                        let why = format!("unknown method {}", method);
+                        self.reply_error(id, METHOD_NOT_FOUND, why)?;
+                    }
+                },
+                // these only fail when writing to the client does
+                None => self.notification(method, &msg["params"])?,
+            }
+        }
+        Ok(1)
+    }
+
+    fn reply_error(&mut self, id: &Json, code: i64, message: String) -> io::Result<()> {
+        let reply = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": code, "message": message},
+        });
+        write_message(&mut self.out, &reply)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {"name": "rs-lox", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.symbols(params),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string())
+            }
+            "textDocument/didChange" => {
+                // full sync, the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => self.update(uri, text.to_string()),
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(uri);
+                self.publish(uri, vec![])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let (errors, analysis) = check(&text);
+        let diagnostics = errors.iter().map(|e| diagnostic(&text, e)).collect();
+
+        let last_good = match &analysis {
+            Some(a) => Some((text.clone(), a.clone())),
+            None => self.docs.remove(uri).and_then(|d| d.last_good),
+        };
+        self.docs.insert(
+            uri.to_string(),
+            Document {
+                text,
+                analysis,
+                last_good,
+            },
+        );
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        });
+        write_message(&mut self.out, &msg)
+    }
+
+    fn completion(&self, params: &Json) -> Json {
+        let mut items: Vec<Json> = KEYWORDS
+            .iter()
+            .map(|kw| json!({"label": kw, "kind": COMPLETION_KEYWORD}))
+            .collect();
+
+        if let Some((doc, pos)) = self.at(params) {
+            let current = doc.analysis.as_ref().map(|a| (doc.text.as_str(), a));
+            let good = doc.last_good.as_ref().map(|(t, a)| (t.as_str(), a));
+            if let Some((text, analysis)) = current.or(good) {
+                for sym in analysis.visible_at(pos.min(text.len())) {
+                    items.push(json!({
+                        "label": sym.name,
+                        "kind": COMPLETION_VARIABLE,
+                        "detail": kind_name(sym),
+                    }));
+                }
+            }
+        }
+        Json::Array(items)
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let Some((doc, sym)) = self.symbol(params) else {
+            return Json::Null;
+        };
+        let decl = &doc.text[sym.stmt.start..sym.stmt.end];
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```lox\n{}\n```\n{} variable", decl, kind_name(sym)),
+            },
+            "range": range(&doc.text, sym.decl.start, sym.decl.end),
+        })
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        match self.symbol(params) {
+            Some((doc, sym)) => json!({
+                "uri": params["textDocument"]["uri"],
+                "range": range(&doc.text, sym.decl.start, sym.decl.end),
+            }),
+            None => Json::Null,
+        }
+    }
+
+    fn symbols(&self, params: &Json) -> Json {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(doc) = self.docs.get(uri) else {
+            return Json::Array(vec![]);
+        };
+        let Some(analysis) = &doc.analysis else {
+            return Json::Array(vec![]);
+        };
+        let symbols = analysis
+            .symbols
+            .iter()
+            .map(|sym| {
+                json!({
+                    "name": sym.name,
+                    "detail": kind_name(sym),
+                    "kind": SYMBOL_VARIABLE,
+                    "range": range(&doc.text, sym.stmt.start, sym.stmt.end),
+                    "selectionRange": range(&doc.text, sym.decl.start, sym.decl.end),
+                })
+            })
+            .collect();
+        Json::Array(symbols)
+    }
+
+    /// Document and offset a `TextDocumentPositionParams` points at
+    fn at(&self, params: &Json) -> Option<(&Document, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let doc = self.docs.get(uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        Some((doc, offset(&doc.text, line, character)))
+    }
+
+    fn symbol(&self, params: &Json) -> Option<(&Document, &Symbol)> {
+        let (doc, pos) = self.at(params)?;
+        let sym = doc.analysis.as_ref()?.symbol_at(pos)?;
+        Some((doc, sym))
+    }
+}
+
+/// All the errors in the source, and the analysis if it compiles
+fn check(source: &str) -> (Vec<CompileError>, Option<Analysis>) {
+    match cst::parse(source) {
+        Err(e) => return (vec![e], None),
+        Ok(tree) if !tree.errors.is_empty() => return (tree.errors, None),
+        Ok(_) => {}
+    }
+
+    let mut scanner = match Scanner::from_str(source) {
+        Ok(scanner) => scanner,
+        Err(e) => return (vec![e], None),
+    };
+    let program = match Parser::init(&mut scanner).parse() {
+        Ok(program) => program,
+        Err(e) => return (vec![e], None),
+    };
+    let mut chunk = Chunk::new();
+    match CodeGen::init(&mut chunk).generate(&program) {
+        Ok(_) => (vec![], Some(Analysis::of(&program))),
+        Err(e) => (vec![e], None),
+    }
+}
+
+fn diagnostic(source: &str, err: &CompileError) -> Json {
+    let span = err.span().unwrap_or(0..0);
+    json!({
+        "range": range(source, span.start, span.end),
+        "severity": SEVERITY_ERROR,
+        "source": "rs-lox",
+        "message": err.message(),
+    })
+}
+
+fn kind_name(sym: &Symbol) -> &'static str {
+    match sym.kind {
+        SymbolKind::Global => "global",
+        SymbolKind::Local => "local",
+    }
+}
+
+fn range(source: &str, start: usize, end: usize) -> Json {
+    json!({"start": position(source, start), "end": position(source, end)})
+}
+
+/// zero based line and character of a byte offset
+fn position(source: &str, pos: usize) -> Json {
+    let before = &source.as_bytes()[..pos.min(source.len())];
+    let line = before.iter().filter(|&&b| b == b'\n').count();
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    json!({"line": line, "character": before.len() - line_start})
+}
+
+fn offset(source: &str, line: usize, character: usize) -> usize {
+    let mut start = 0;
+    for _ in 0..line {
+        match source[start..].find('\n') {
+            Some(nl) => start += nl + 1,
+            None => return source.len(),
+        }
+    }
+    let line_len = source[start..].find('\n').unwrap_or(source.len() - start);
+    start + character.min(line_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///test.lox";
+
+    /// Feed the messages to a fresh server, return what it wrote back and the exit code
+    fn session(msgs: &[Json]) -> (Vec<Json>, i32) {
+        let mut input = vec![];
+        for msg in msgs {
+            write_message(&mut input, msg).unwrap();
+        }
+        serve_bytes(input)
+    }
+
+    fn serve_bytes(input: Vec<u8>) -> (Vec<Json>, i32) {
+        let mut server = Server::new(vec![]);
+        let code = server.serve(&mut Cursor::new(input)).unwrap();
+        let mut output = Cursor::new(server.out);
+        let mut replies = vec![];
+        while let Some(msg) = read_message(&mut output).unwrap() {
+            replies.push(msg);
+        }
+        (replies, code)
+    }
+
+    fn open(text: &str) -> Json {
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": URI, "languageId": "lox", "version": 1, "text": text}}})
+    }
+
+    fn at(id: u32, method: &str, line: u32, character: u32) -> Json {
+        json!({"jsonrpc": "2.0", "id": id, "method": method,
+            "params": {"textDocument": {"uri": URI}, "position": {"line": line, "character": character}}})
+    }
+
+    fn reply(replies: &[Json], id: u32) -> &Json {
+        let msg = replies.iter().find(|m| m["id"] == id).expect("no reply");
+        &msg["result"]
+    }
+
+    fn labels(items: &Json) -> Vec<&str> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|i| i["kind"] == COMPLETION_VARIABLE)
+            .map(|i| i["label"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (replies, code) = session(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "workspace/symbol", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(reply(&replies, 1)["capabilities"]["hoverProvider"], true);
+        let unknown = replies.iter().find(|m| m["id"] == 2).unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(replies.len(), 3);
+
+        let (_, code) = session(&[json!({"jsonrpc": "2.0", "method": "exit"})]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn survives_bad_frames() {
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        input.extend_from_slice(b"garbage\r\n\r\n");
+        write_message(&mut input, &json!([1, 2])).unwrap();
+        write_message(
+            &mut input,
+            &json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        )
+        .unwrap();
+        let (replies, code) = serve_bytes(input);
+        // stdin closed without `exit`
+        assert_eq!(code, 1);
+        assert_eq!(replies.len(), 4, "{:?}", replies);
+        assert_eq!(replies[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(replies[0]["id"], Json::Null);
+        assert_eq!(replies[1]["error"]["code"], PARSE_ERROR);
+        assert_eq!(replies[2]["error"]["code"], INVALID_REQUEST);
+        assert_eq!(reply(&replies, 1)["capabilities"]["hoverProvider"], true);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let change = json!({"jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": {"textDocument": {"uri": URI, "version": 2}, "contentChanges": [{"text": "{ var a; var a; }"}]}});
+        let (replies, _) = session(&[open("var a = 1\nprint ;\nprint a;"), change]);
+
+        let first = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(first.len(), 2, "{:?}", first);
+        assert_eq!(
+            first[0]["range"]["start"],
+            json!({"line": 1, "character": 0})
+        );
+        assert_eq!(
+            first[1]["range"]["start"],
+            json!({"line": 1, "character": 6})
+        );
+        assert_eq!(first[1]["message"], "Bad Expression");
+
+        let second = replies[1]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(
+            second[0]["message"],
+            "Variable a is already declared in this scope"
+        );
+        assert_eq!(second[0]["range"]["start"]["character"], 13);
+    }
+
+    #[test]
+    fn navigation() {
+        let text = "var g = 1;\n{\n    var l = g;\n    print l;\n}\nprint g;\n";
+        let (replies, _) = session(&[
+            open(text),
+            at(1, "textDocument/completion", 3, 10),
+            at(2, "textDocument/completion", 5, 6),
+            at(3, "textDocument/hover", 3, 10),
+            at(4, "textDocument/definition", 5, 6),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "textDocument/documentSymbol",
+                "params": {"textDocument": {"uri": URI}}}),
+            at(6, "textDocument/hover", 3, 5),
+        ]);
+        assert!(replies[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(labels(reply(&replies, 1)), ["l", "g"]);
+        assert_eq!(labels(reply(&replies, 2)), ["g"]);
+        assert!(reply(&replies, 1)
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|i| i["label"] == "while"));
+
+        let hover = reply(&replies, 3);
+        assert_eq!(
+            hover["contents"]["value"],
+            "```lox\nvar l = g;\n```\nlocal variable"
+        );
+
+        let def = reply(&replies, 4);
+        assert_eq!(def["uri"], URI);
+        assert_eq!(def["range"]["start"], json!({"line": 0, "character": 4}));
+
+        let syms = reply(&replies, 5).as_array().unwrap();
+        assert_eq!(syms.len(), 2);
+        assert_eq!(syms[1]["name"], "l");
+        assert_eq!(
+            syms[1]["selectionRange"]["start"],
+            json!({"line": 2, "character": 8})
+        );
+
+        assert_eq!(*reply(&replies, 6), Json::Null);
+    }
+
+    #[test]
+    fn completion_survives_broken_edits() {
+        let change = json!({"jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": {"textDocument": {"uri": URI, "version": 2},
+                "contentChanges": [{"text": "{ var inner = 1; print in }"}]}});
+        let (replies, _) = session(&[
+            open("{ var inner = 1; print inner; }"),
+            change,
+            at(1, "textDocument/completion", 0, 25),
+        ]);
+        assert_eq!(labels(reply(&replies, 1)), ["inner"]);
+    }
+}
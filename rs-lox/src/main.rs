@@ -1,12 +1,26 @@
+mod cli;
+mod coverage;
+mod dap;
+mod debugger;
+mod disasm;
 mod errors;
 
+mod fmt;
+mod highlight;
+mod lint;
+mod loxc;
+mod lsp;
+mod profile;
+mod repl;
 mod runtime;
 mod session;
+mod sources;
+mod test_runner;
+mod wire;
 
-use session::RuntimeContext;
 use std::env;
-use std::fs;
 use std::mem::{align_of, size_of};
+use std::process;
 
 use lang::OpCode;
 use values::Value;
@@ -27,78 +41,17 @@ fn shitcode() {
     println!("Size of Pointer Vec is {} bytes", size_of::<Vec<*mut u8>>());
 }
 
-fn interpret(source: &str, debug: bool) {
-    let mut runtime = RuntimeContext::start(debug);
-    let ch_id = match runtime.compile(source) {
-        Ok(idx) => idx,
-        Err(e) => {
-            println!(" Error: [\n\t {} \n]", e);
-            return;
-        }
-    };
-    match runtime.exec(ch_id) {
-        Ok(_) => {}
-        Err(e) => {
-            println!(" Error: [ \n\t {} \n ]", e);
-            return;
-        }
-    }
-}
-
-fn repl_callback(input: &str) -> Vec<String> {
-    let ret: Vec<&str>;
-    if input.starts_with("g") {
-        ret = vec!["grisha", "gregory"];
-    } else {
-        ret = vec!["einav"];
-    }
-
-    ret.iter().map(|s| s.to_string()).collect()
-}
-
-fn repl() {
-    // linenoise::set_callback(repl_callback);
-    linenoise::set_multiline(3);
-
-    let mut runtime = RuntimeContext::start(false);
-
-    loop {
-        let val = linenoise::input("> ");
-        if let Some(cmd) = val {
-            match cmd.as_str() {
-                ":q" => {
-                    runtime.debug_report();
-                    break;
-                }
-                ":verbose" => {}
-                // s => interpret(s),
-                s => {
-                    let expr_id = runtime.compile(s);
-                    match expr_id {
-                        Err(e) => println!("  Err: [{}]", e),
-                        Ok(idx) => {
-                            if let Err(e) = runtime.exec(idx) {
-                                println!("  Err: [\n\t {} \n]", e);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-}
-
 fn main() {
-    match (env::args().nth(1), env::args().nth(2)) {
-        (None, _) => repl(),
-        (Some(txt), debug) => {
-            if txt == "info" {
-                shitcode();
-                return;
-            }
-            let dbg = debug.is_some();
-            let source = fs::read_to_string(txt).unwrap();
-            interpret(&source, dbg);
-        }
+    let argv: Vec<String> = env::args().skip(1).collect();
+    match argv.first().map(|s| s.as_str()) {
+        Some("compile") => process::exit(loxc::run(&argv[1..])),
+        Some("dap") => process::exit(dap::run()),
+        Some("debug") => process::exit(debugger::run(&argv[1..])),
+        Some("fmt") => process::exit(fmt::run(&argv[1..])),
+        Some("lint") => process::exit(lint::run(&argv[1..])),
+        Some("test") => process::exit(test_runner::run(&argv[1..])),
+        Some("lsp") => process::exit(lsp::run()),
+        Some("info") => shitcode(),
+        _ => process::exit(cli::run(&argv)),
     }
 }
@@ -0,0 +1,621 @@
+//! The interactive prompt, `rs-lox` without a script.
+//!
+//! Declarations and statements run as they would in a script. A bare expression (no `;`) is
+//! evaluated and its value printed. Errors go to the diagnostics sink of the runtime, stderr
+//! unless the REPL was given other sinks. Input with an open brace, paren or string continues on the
+//! next line. Lines starting with `:` are commands for the REPL itself, see [`HELP`].
+//!
+//! The input is colored as it is typed, see [`crate::highlight`].
+//! Tab completes keywords, globals and commands. The history is kept in [`HISTORY_FILE`] in the
+//! home directory, Ctrl-R searches it.
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use thiserror::Error;
+
+use compiler::Parser;
+use lang::{Scanner, TokenType};
+use values::Value;
+
+use crate::cli::EXIT_IO;
+use crate::disasm::disassemble;
+use crate::errors::{COMPError, CompileError, RuntimeError};
+use crate::highlight::highlight;
+use crate::lsp::KEYWORDS;
+use crate::runtime::Output;
+use crate::session::{ChunkAddr, RuntimeContext};
+
+const PROMPT: &str = "> ";
+const CONTINUE_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".rs_lox_history";
+
+const HELP: &str = "\
+:dis CODE          show the bytecode of an expression or statements, without running them
+:globals           list the global variables
+:stack             show the values on the VM stack
+:load FILE         run a lox file in this session
+:reset             forget all the globals and start over
+:time              toggle showing how long every input took
+:verbose           toggle tracing every op as it runs
+:help              show this help
+:quit | :q         leave the REPL
+
+Tab completes keywords, globals and commands, Ctrl-R searches the history";
+
+const COMMANDS: [&str; 10] = [
+    ":dis", ":globals", ":stack", ":load", ":reset", ":time", ":verbose", ":help", ":quit", ":q",
+];
+
+#[derive(Debug, Error)]
+pub enum ReplError {
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+/// A compiled input
+enum Compiled {
+    /// bare expression, its value is left on the stack
+    Expr(ChunkAddr),
+    Program(ChunkAddr),
+}
+
+pub struct Repl<W: Write> {
+    runtime: RuntimeContext,
+    out: W,
+    /// show how long every input took
+    timing: bool,
+    /// trace the ops as they run
+    verbose: bool,
+    /// the program called `exit(code)`, the session is over
+    exit: Option<i32>,
+}
+
+impl<W: Write> Repl<W> {
+    pub fn new(out: W) -> Self {
+        Self::with_output(out, Output::stdio())
+    }
+
+    /// `out` gets the values and what the commands show, `output` is the runtime's
+    pub fn with_output(out: W, output: Output) -> Self {
+        Self {
+            runtime: RuntimeContext::with_output(false, output),
+            out,
+            timing: false,
+            verbose: false,
+            exit: None,
+        }
+    }
+
+    /// Names of the globals defined so far, and the ones the runtime provides
+    pub fn global_names(&self) -> Vec<String> {
+        let defined = self.runtime.vm().globals().iter().map(|(name, _)| name);
+        let builtin = self.runtime.vm().builtin_names();
+        defined
+            .cloned()
+            .chain(builtin.into_iter().map(String::from))
+            .collect()
+    }
+
+    /// The code the program passed to `exit`, if it did
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Handle a complete input, returns false when the session is over
+    pub fn handle(&mut self, input: &str) -> io::Result<bool> {
+        let trimmed = input.trim();
+        let go_on = if trimmed.starts_with(':') {
+            self.command(trimmed)?
+        } else {
+            if !trimmed.is_empty() {
+                self.run_source(input)?;
+            }
+            true
+        };
+        Ok(go_on && self.exit.is_none())
+    }
+
+    /// Run a complete input. Returns the value if it was a bare expression
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, ReplError> {
+        match self.compile(source)? {
+            Compiled::Expr(addr) => Ok(Some(self.runtime.exec_expr(addr)?)),
+            Compiled::Program(addr) => {
+                self.runtime.exec(addr)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn compile(&mut self, source: &str) -> COMPError<Compiled> {
+        let mut scanner = Scanner::from_str(source)?;
+        if let Ok(expr) = Parser::init(&mut scanner).parse_expression() {
+            return Ok(Compiled::Expr(self.runtime.compile_expr(&expr)?));
+        }
+        Ok(Compiled::Program(self.runtime.compile(source)?))
+    }
+
+    fn run_source(&mut self, source: &str) -> io::Result<()> {
+        let start = Instant::now();
+        let res = self.eval(source);
+        let took = start.elapsed();
+        match res {
+            Ok(Some(val)) => writeln!(self.out, "{}", val)?,
+            Ok(None) => {}
+            Err(ReplError::Compile(e)) => writeln!(self.diag(), "  Err: [{}]", e)?,
+            Err(ReplError::Runtime(RuntimeError::Exit(code))) => self.exit = Some(code),
+            Err(ReplError::Runtime(e)) => self.runtime.report(&e, Some(source)),
+        }
+        if self.timing {
+            writeln!(self.out, "took {:.3?}", took)?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match cmd {
+            ":dis" => match self.compile(arg) {
+                Ok(Compiled::Expr(addr) | Compiled::Program(addr)) => {
+                    let chunk = self.runtime.get_chunk(addr);
+                    write!(self.out, "{}", disassemble(&chunk))?
+                }
+                Err(e) => writeln!(self.diag(), "  Err: [{}]", e)?,
+            },
+            ":globals" => {
+                let mut globals: Vec<_> = self.runtime.vm().globals().iter().collect();
+                globals.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, val) in globals {
+                    writeln!(self.out, "{} = {}", name, val)?;
+                }
+            }
+            ":stack" => {
+                let values: Vec<String> = self
+                    .runtime
+                    .vm()
+                    .stack_values()
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect();
+                writeln!(self.out, "[{}]", values.join(", "))?
+            }
+            ":load" => match fs::read_to_string(arg) {
+                Ok(source) => self.run_source(&source)?,
+                Err(e) => writeln!(self.diag(), "{}: {}", arg, e)?,
+            },
+            ":reset" => {
+                // a new runtime, but it keeps writing where the old one did
+                let output = self.runtime.set_output(Output::new(io::sink(), io::sink()));
+                self.runtime = RuntimeContext::with_output(self.verbose, output);
+                writeln!(self.out, "session cleared")?
+            }
+            ":time" => {
+                self.timing = !self.timing;
+                writeln!(self.out, "timing {}", on_off(self.timing))?
+            }
+            ":verbose" => {
+                self.verbose = !self.verbose;
+                self.runtime.set_debug(self.verbose);
+                writeln!(self.out, "verbose {}", on_off(self.verbose))?
+            }
+            ":help" => writeln!(self.out, "{}", HELP)?,
+            ":quit" | ":q" => {
+                self.runtime.debug_report();
+                return Ok(false);
+            }
+            _ => match suggest(cmd) {
+                Some(known) => writeln!(
+                    self.diag(),
+                    "unknown command `{}`, did you mean `{}`?",
+                    cmd,
+                    known
+                )?,
+                None => writeln!(self.diag(), "unknown command `{}`, try `:help`", cmd)?,
+            },
+        }
+        Ok(true)
+    }
+
+    fn diag(&mut self) -> &mut dyn Write {
+        self.runtime.output().diag()
+    }
+}
+
+/// Tab completion and colors for the prompt
+struct LoxHelper {
+    globals: Vec<String>,
+    color: bool,
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(completions(line, pos, &self.globals))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        match highlight(line).filter(|_| self.color) {
+            Some(colored) => Cow::Owned(colored),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    /// Every key can change how the line scans, like opening a string
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        self.color
+    }
+}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+pub fn run() -> i32 {
+    let mut editor = match Editor::<LoxHelper, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("repl: {}", e);
+            return EXIT_IO;
+        }
+    };
+    editor.set_helper(Some(LoxHelper {
+        globals: vec![],
+        // https://no-color.org
+        color: env::var_os("NO_COLOR").is_none(),
+    }));
+    let history = history_file();
+    if let Some(path) = history.as_ref() {
+        // there is no history the first time around
+        let _ = editor.load_history(path);
+    }
+
+    let mut repl = Repl::new(io::stdout());
+    let mut code = 0;
+    while let Some(source) = read_input(&mut editor) {
+        match repl.handle(&source) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("repl: {}", e);
+                code = EXIT_IO;
+                break;
+            }
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.globals = repl.global_names();
+        }
+    }
+    code = repl.exit_code().unwrap_or(code);
+
+    if let Some(path) = history.as_ref() {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("failed to save the history to {}: {}", path.display(), e);
+        }
+    }
+    code
+}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+fn on_off(flag: bool) -> &'static str {
+    if flag {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// The command that was probably meant: the only one starting with `cmd`, or one a typo away
+fn suggest(cmd: &str) -> Option<&'static str> {
+    if cmd.len() < 2 {
+        return None;
+    }
+    let mut prefixed = COMMANDS.iter().filter(|known| known.starts_with(cmd));
+    if let (Some(known), None) = (prefixed.next(), prefixed.next()) {
+        return Some(known);
+    }
+    COMMANDS
+        .iter()
+        .map(|known| (edit_distance(cmd, known), *known))
+        .filter(|(dist, _)| *dist <= 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = prev[j] + (ca != *cb) as usize;
+            cur.push(replace.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Read lines until the input is complete, `None` at the end of the input.
+/// Ctrl-C drops what was typed so far
+fn read_input(editor: &mut Editor<LoxHelper, DefaultHistory>) -> Option<String> {
+    let mut source = match editor.readline(PROMPT) {
+        Ok(line) => line,
+        Err(ReadlineError::Interrupted) => return Some(String::new()),
+        Err(_) => return None,
+    };
+    while is_incomplete(&source) {
+        match editor.readline(CONTINUE_PROMPT) {
+            Ok(line) => {
+                source.push('\n');
+                source.push_str(&line);
+            }
+            Err(ReadlineError::Interrupted) => return Some(String::new()),
+            Err(_) => break,
+        }
+    }
+    if !source.trim().is_empty() {
+        let _ = editor.add_history_entry(source.as_str());
+    }
+    Some(source)
+}
+
+/// Where the word before the cursor starts, and what it may be completed to.
+/// Commands complete at the start of the line, keywords and globals everywhere else
+fn completions(line: &str, pos: usize, globals: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == ':'))
+        .map_or(0, |idx| idx + 1);
+    let word = &line[start..pos];
+    let mut found: Vec<String> = match word.strip_prefix(':') {
+        _ if word.is_empty() => vec![],
+        Some(_) if start == 0 => COMMANDS.iter().map(|cmd| cmd.to_string()).collect(),
+        Some(_) => vec![],
+        None => KEYWORDS
+            .iter()
+            .map(|kw| kw.to_string())
+            .chain(globals.iter().cloned())
+            .collect(),
+    };
+    found.retain(|candidate| candidate.starts_with(word) && candidate != word);
+    found.sort();
+    found.dedup();
+    (start, found)
+}
+
+/// Does the input stop in the middle of something: an open brace, paren or bracket, or a string
+fn is_incomplete(source: &str) -> bool {
+    let Ok(mut scanner) = Scanner::from_str(source) else {
+        return false;
+    };
+    let mut depth = 0;
+    loop {
+        match scanner.scan_token() {
+            Ok(tok) => match tok.ty {
+                TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                    depth -= 1
+                }
+                TokenType::EoF => return depth > 0,
+                _ => {}
+            },
+            // an unterminated string runs until the end of the input
+            Err(_) => return scanner.ascii_chars.get(scanner.start_pos) == Some(&b'"'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Captured;
+
+    /// What the REPL showed and what went to the diagnostics sink
+    fn session(inputs: &[&str]) -> (String, String) {
+        let diag = Captured::default();
+        let mut repl = Repl::with_output(vec![], Output::new(io::sink(), diag.clone()));
+        for input in inputs {
+            if !repl.handle(input).unwrap() {
+                break;
+            }
+        }
+        (String::from_utf8(repl.out).unwrap(), diag.take())
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for source in [
+            "{",
+            "if (a) {\n print a;",
+            "print (1 +",
+            "print \"abc",
+            "{ \"}",
+            "args[",
+        ] {
+            assert!(is_incomplete(source), "{:?}", source);
+        }
+        for source in ["", "1 + 2", "{ print 1; }", "print \"a{\";", ")", "print @"] {
+            assert!(!is_incomplete(source), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn expressions_are_echoed() {
+        let mut repl = Repl::new(vec![]);
+        assert!(matches!(repl.eval("var a = 1;"), Ok(None)));
+        assert!(matches!(repl.eval("a + 2"), Ok(Some(Value::Float(n))) if n == 3.0));
+        assert!(matches!(repl.eval("a = \"x\""), Ok(Some(Value::String(s))) if s == "x"));
+        assert!(matches!(
+            repl.eval("{\n var b = a;\n a = b + b;\n}"),
+            Ok(None)
+        ));
+        assert!(matches!(repl.eval("a"), Ok(Some(Value::String(s))) if s == "xx"));
+
+        assert!(matches!(repl.eval("print a"), Err(ReplError::Compile(_))));
+        assert!(matches!(repl.eval("nope + 1"), Err(ReplError::Runtime(_))));
+    }
+
+    #[test]
+    fn commands() {
+        let (out, diag) = session(&[
+            "var b = 2;",
+            "var a = \"x\";",
+            ":globals",
+            ":dis a + 1",
+            ":stack",
+            ":reset",
+            ":globals",
+            "b",
+            ":glob",
+            ":frobnicate",
+            ":q",
+            "1",
+        ]);
+        let expected = "\
+a = x
+b = 2
+0000    1 GET_GLOBAL(0)      \"a\"
+0002    | CONSTANT(1)        1
+0004    | ADD
+0005    | RETURN
+[]
+session cleared
+";
+        assert_eq!(out, expected);
+        // the session after `:reset` still reports to the same place, `:q` adds the stack
+        let expected = " Error: [ \n\t Unknown variable b \n ]
+ at line 1:1
+ 1 | b
+   | ^
+unknown command `:glob`, did you mean `:globals`?
+unknown command `:frobnicate`, try `:help`
+ []
+";
+        assert_eq!(diag, expected);
+    }
+
+    #[test]
+    fn recovers_from_runtime_errors() {
+        let mut repl = Repl::new(vec![]);
+        let mut value = |source: &str| match repl.eval(source) {
+            Ok(val) => Ok(val.map(|v| v.to_string())),
+            Err(e) => Err(e.to_string()),
+        };
+        assert_eq!(value("var a = 1;"), Ok(None));
+        // fails with two locals and a temporary on the stack
+        assert_eq!(
+            value("{ var x = 10; var y = x; print y + (x + nope); }"),
+            Err("Unknown variable nope".to_string())
+        );
+        // the locals of this block must not land on top of the old ones
+        assert_eq!(value("{ var q = 3; a = q; }"), Ok(None));
+        assert_eq!(value("a"), Ok(Some("3".to_string())));
+        assert!(value("a + (1 - \"x\")").is_err());
+        assert!(value("-\"x\"").is_err());
+        // statements before the error ran, the ones after it didn't
+        assert!(value("var b = a * 2; b = b + missing; var c = 1;").is_err());
+        assert_eq!(value("b"), Ok(Some("6".to_string())));
+        assert_eq!(value("b - a"), Ok(Some("3".to_string())));
+        assert!(value("c").is_err());
+
+        assert!(repl.runtime.vm().stack_values().is_empty());
+        assert_eq!(repl.runtime.vm().ip(), 0);
+        repl.handle(":stack").unwrap();
+        assert_eq!(String::from_utf8(repl.out).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn non_bool_conditions_are_errors() {
+        let (out, diag) = session(&[
+            "var a = 1;",
+            "if (a) print 2;",
+            "a and true",
+            "{ var x = 1; while (x) x = nil; }",
+            "a + 1",
+        ]);
+        let errors = diag
+            .matches("Condition must be a bool, got Float(1.0)")
+            .count();
+        assert_eq!(errors, 3, "{}", diag);
+        assert_eq!(out, "2\n");
+    }
+
+    #[test]
+    fn toggles_and_load() {
+        let path = std::env::temp_dir().join("rs-lox-repl-load.lox");
+        fs::write(&path, "var loaded = 40 + 2;").unwrap();
+        let load = format!(":load {}", path.to_string_lossy());
+        let (out, diag) = session(&[&load, "loaded", ":time", "1", ":time", ":load /nope.lox"]);
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[..4], ["42", "timing on", "1", lines[3]]);
+        assert!(lines[3].starts_with("took "), "{}", out);
+        assert_eq!(lines[4], "timing off");
+        assert_eq!(lines.len(), 5, "{}", out);
+        assert!(diag.starts_with("/nope.lox: "), "{}", diag);
+    }
+
+    #[test]
+    fn exit_ends_the_session() {
+        let mut repl = Repl::new(vec![]);
+        assert!(repl.handle("args").unwrap());
+        assert!(repl.handle("getenv(\"RS_LOX_SURELY_NOT_SET\")").unwrap());
+        assert!(!repl.handle("exit(4);").unwrap());
+        assert_eq!(repl.exit_code(), Some(4));
+        assert_eq!(String::from_utf8(repl.out).unwrap(), "[]\nNil\n");
+    }
+
+    #[test]
+    fn completion() {
+        let globals = ["total".to_string(), "tally".to_string()];
+        let complete = |line: &str| {
+            let (start, found) = completions(line, line.len(), &globals);
+            (start, found.join(" "))
+        };
+        assert_eq!(complete("print ta"), (6, "tally".to_string()));
+        assert_eq!(complete("t"), (0, "tally test this total true".to_string()));
+        assert_eq!(complete("var x = (fa"), (9, "false".to_string()));
+        assert_eq!(complete(":g"), (0, ":globals".to_string()));
+        assert_eq!(complete(":q"), (0, ":quit".to_string()));
+        assert_eq!(complete("print :g"), (6, String::new()));
+        assert_eq!(complete("print "), (6, String::new()));
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggest(":glo"), Some(":globals"));
+        assert_eq!(suggest(":stakc"), Some(":stack"));
+        assert_eq!(suggest(":verbsoe"), Some(":verbose"));
+        assert_eq!(suggest(":"), None);
+        assert_eq!(suggest(":xyzzy"), None);
+    }
+}
@@ -1,20 +1,48 @@
 use super::*;
 
-use super::ops::{exec_binary, exec_unary};
+use super::ops::{exec_binary, exec_call, exec_index, exec_invoke, exec_unary};
 
 impl VM {
     pub fn run(&mut self) -> RTError<()> {
+        self.run_hooked(None).map(|_| ())
+    }
+
+    /// Run with a hook that gets to look at the VM before every op, and can pause it.
+    /// Calling this again after a pause continues from the same op
+    pub fn run_hooked(&mut self, hook: Option<&mut dyn Hook>) -> RTError<Exit> {
+        let res = self.run_ops(hook);
+        // whatever way the run ended, the host gets to see what it printed so far
+        let flushed = self.output.flush();
+        let exit = res?;
+        flushed?;
+        Ok(exit)
+    }
+
+    fn run_ops(&mut self, mut hook: Option<&mut dyn Hook>) -> RTError<Exit> {
         use OpCode::*;
 
+        if !self.verified {
+            // the chunk may start on top of values that are already there, like the locals of a paused program
+            let depth = self.stack.borrow().len();
+            self.cur_chunk().verify_from(depth)?;
+            self.verified = true;
+        }
         loop {
-            let op = self.read_byte();
+            if let Some(hook) = hook.as_mut() {
+                if hook.before_op(self) == Control::Pause {
+                    return Ok(Exit::Paused);
+                }
+            }
+            let (op, len) = self.read_byte();
             if self.debug {
-                println!(
+                let line = self.line();
+                writeln!(
+                    self.output.diag(),
                     "\t {}) {:?} <- line {}",
                     self.ip,
                     op,
-                    self.cur_chunk().line_nums[self.ip]
-                )
+                    line
+                )?;
             };
             match op {
                 RETURN => {
@@ -22,50 +50,48 @@ impl VM {
                 }
                 CONSTANT(idx) => {
                     let val = self.cur_chunk().read_const(idx).clone();
-                    self.push(val);
+                    self.push(val)?;
                 }
                 NEGATE | NOT => {
                     let mut s = self.stack.borrow_mut();
-                    exec_unary(op, &mut s).unwrap();
+                    exec_unary(op, &mut s)?;
                 }
                 lit @ (NIL | FALSE | TRUE) => {
-                    let val = Value::try_from(lit);
-                    if val.is_ok() {
-                        self.push(val.unwrap());
+                    if let Ok(val) = Value::try_from(lit) {
+                        self.push(val)?;
                     }
                 }
 
                 ADD | SUB | MUL | DIV | LESS | GREATER | EQUAL | AND | OR => {
                     let mut s = self.stack.borrow_mut();
 
-                    exec_binary(op, &mut s).expect(&format!(
-                        "Failed on line {}",
-                        self.cur_chunk().get_line_num(self.ip)
-                    ));
+                    exec_binary(op, &mut s)?;
                 }
 
                 PRINT => {
-                    println!("{}", self.pop())
+                    let val = self.pop()?;
+                    writeln!(self.output.out(), "{}", val)?;
                 }
                 POP => {
-                    self.pop();
+                    self.pop()?;
                 }
                 DEFINE_GLOBAL(ident_idx) => {
                     // got in trouble with the slices. they don't live long enough (according to borrow checker).
                     // need some interning mechanism...
                     let key = self._read_ident(ident_idx);
-                    let val = self.pop();
+                    let val = self.pop()?;
                     self.globals.put(key, val);
                 }
                 GET_GLOBAL(ident_idx) => {
                     let key = self._read_ident(ident_idx);
-                    let val = self.globals.get(&key);
-                    if val.is_none() {
-                        self.debug_dump();
+                    let val = self.globals.get(&key).cloned();
+                    let Some(val) = val.or_else(|| self.builtin(&key)) else {
+                        if self.debug {
+                            self.debug_dump()?;
+                        }
                         return Err(RuntimeError::UnknownVariable(key));
-                    }
-                    let val = val.unwrap().clone();
-                    self.push(val);
+                    };
+                    self.push(val)?;
                 }
                 GET_LOCAL(slot) => {
                     // expressions leave stuff on the stack, but we don't allow naked expression anymore
@@ -75,9 +101,9 @@ impl VM {
                         .stack
                         .borrow_mut()
                         .peek_at(slot as usize)
-                        .expect("Local Slot in invalid stack location???")
+                        .ok_or(RuntimeError::BadLocal(slot))?
                         .clone();
-                    self.push(val);
+                    self.push(val)?;
                 }
                 SET_GLOBAL(ident_idx) => {
                     let ident_ = self._read_ident(ident_idx);
@@ -94,15 +120,14 @@ impl VM {
                         .stack
                         .borrow_mut()
                         .peek_at(slot as usize)
-                        .expect("Local Slot in invalid stack location???") = val;
+                        .ok_or(RuntimeError::BadLocal(slot))? = val;
                 }
                 JUMP_IF_FALSE(new_ip) => {
-                    let last_val: bool = self
-                        .peek()
-                        .unwrap()
-                        .try_into()
-                        .expect("Trying to create  a bool from non bool Value");
-                    if !last_val {
+                    let cond = match self.peek()? {
+                        Value::Bool(cond) => cond,
+                        other => return Err(RuntimeError::NotABool(other)),
+                    };
+                    if !cond {
                         self.ip = new_ip as usize;
                         continue;
                     }
@@ -111,15 +136,43 @@ impl VM {
                     self.ip = new_ip as usize;
                     continue;
                 }
+                ASSERT => {
+                    let val = self.pop()?;
+                    if matches!(val, Value::Nil | Value::Bool(false)) {
+                        return Err(RuntimeError::AssertFailed(self.line(), val));
+                    }
+                }
+                CALL(argc) => {
+                    let mut s = self.stack.borrow_mut();
+                    exec_call(argc, &mut s, self.output.out())?;
+                }
+                INVOKE(name_idx, argc) => {
+                    let name = self._read_ident(name_idx);
+                    let mut s = self.stack.borrow_mut();
+                    exec_invoke(&name, argc, &mut s)?;
+                }
+                INDEX => {
+                    let mut s = self.stack.borrow_mut();
+                    exec_index(&mut s)?;
+                }
+                ASSERT_EQ => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    // mixed types compare to Nil, those are never equal
+                    if !matches!(lhs.eq(rhs.clone()), Value::Bool(true)) {
+                        return Err(RuntimeError::AssertEqFailed(self.line(), lhs, rhs));
+                    }
+                }
             }
 
             if self.debug {
-                print!("\t");
-                self.show_stack();
+                let stack: Vec<String> =
+                    self.stack_values().iter().map(|v| v.to_string()).collect();
+                writeln!(self.output.diag(), "\t [{}]", stack.join(", "))?;
             };
-            self.ip += 1;
+            self.ip += len;
         }
         // clear chunk
-        Ok(())
+        Ok(Exit::Done)
     }
 }
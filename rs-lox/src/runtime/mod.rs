@@ -1,14 +1,39 @@
 mod eval_loop;
+mod natives;
 mod ops;
+mod output;
+mod strings;
 mod utils;
 
+use natives::Natives;
+pub use output::{Captured, Output};
+
 use std::cell::RefCell;
 
 use crate::errors::{RTError, RuntimeError};
 
 use lang::{ConstIdx, OpCode};
 use values::Value;
-use values::{Chunk, Stack, VarStore};
+use values::{Chunk, SourcePos, Stack, VarStore};
+
+/// What the VM should do after asking the [`Hook`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Pause,
+}
+
+/// How a run ended. A paused VM picks up where it stopped on the next run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Done,
+    Paused,
+}
+
+/// Called before every instruction, lets tools like the debugger watch and stop the VM
+pub trait Hook {
+    fn before_op(&mut self, vm: &VM) -> Control;
+}
 
 pub struct VM {
     chunk: Option<Chunk>,
@@ -16,10 +41,16 @@ pub struct VM {
     stack: RefCell<Stack>,
     globals: VarStore,
     debug: bool,
+    /// the loaded chunk passed the verifier
+    verified: bool,
+    /// what the script sees as `args`
+    args: Vec<Value>,
+    natives: Natives,
+    output: Output,
 }
 
 impl VM {
-    pub fn init(debug: bool) -> Self {
+    pub fn init(debug: bool, output: Output) -> Self {
         // let stack =[Value::Null; STACK_MAX];
         let stack = RefCell::new(Stack::init());
         let globals = VarStore::new();
@@ -29,6 +60,104 @@ impl VM {
             stack,
             globals,
             debug,
+            verified: false,
+            args: vec![],
+            natives: Natives::core(),
+            output,
         }
     }
+
+    /// Names of the globals the runtime provides
+    pub fn builtin_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.natives.names().chain(["args"]).collect();
+        names.sort();
+        names
+    }
+
+    /// Where `print`, traces and error reports go
+    pub fn output(&mut self) -> &mut Output {
+        &mut self.output
+    }
+
+    /// Swap the sinks, the old ones are returned
+    pub fn set_output(&mut self, output: Output) -> Output {
+        std::mem::replace(&mut self.output, output)
+    }
+
+    /// The command line arguments after the script, the `args` global
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args.into_iter().map(Value::String).collect();
+    }
+
+    /// Globals the runtime provides, they are looked up when the program didn't define the name
+    fn builtin(&self, name: &str) -> Option<Value> {
+        match name {
+            "args" => Some(Value::List(self.args.clone())),
+            _ => self.natives.get(name).map(Value::Native),
+        }
+    }
+
+    /// Print every op and the stack as they run
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Address of the next op to run
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Source line of the next op to run
+    pub fn line(&self) -> usize {
+        self.pos().map_or(0, |pos| pos.line as usize)
+    }
+
+    /// Source position of the next op to run. After a runtime error, the op that failed
+    pub fn pos(&self) -> Option<SourcePos> {
+        self.chunk.as_ref()?.get_pos(self.ip)
+    }
+
+    /// How many calls deep we are. There are no functions yet, so everything runs in the top frame
+    pub fn frame_depth(&self) -> usize {
+        0
+    }
+
+    /// Name of the function running right now
+    pub fn frame_name(&self) -> &str {
+        "<script>"
+    }
+
+    pub fn chunk(&self) -> Option<&Chunk> {
+        self.chunk.as_ref()
+    }
+
+    pub fn globals(&self) -> &VarStore {
+        &self.globals
+    }
+
+    /// Locals that are alive right now, by name
+    pub fn locals(&self) -> Vec<(String, Value)> {
+        let Some(chunk) = self.chunk.as_ref() else {
+            return vec![];
+        };
+        let stack = self.stack.borrow();
+        chunk
+            .locals_at(self.ip)
+            .into_iter()
+            .filter_map(|l| Some((l.name.clone(), stack.get(l.slot as usize)?.clone())))
+            .collect()
+    }
+
+    /// Run `chunk` on top of the current state (stack and globals) and return the value it leaves behind.
+    /// The stack and the loaded chunk are restored afterwards, so a paused run can continue
+    pub fn eval(&mut self, chunk: Chunk) -> RTError<Value> {
+        let saved = (self.chunk.replace(chunk), self.ip, self.verified);
+        let height = self.stack_height();
+        self.ip = 0;
+        self.verified = false;
+        let res = self.run().and_then(|_| self.peek());
+        self.truncate_stack(height);
+        (self.chunk, self.ip, self.verified) = saved;
+        res
+    }
 }
@@ -0,0 +1,226 @@
+//! Functions implemented in rust. They are globals that are always there,
+//! a script can still shadow one with a global of the same name.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use values::{Native, NativeError, NativeFn, Value};
+
+type NativeResult = Result<Value, NativeError>;
+
+const CORE: &[(&str, u8, NativeFn)] = &[
+    ("clock", 0, clock),
+    ("type", 1, type_),
+    ("str", 1, str_),
+    ("num", 1, num),
+    ("len", 1, len),
+    ("input", 0, input),
+    ("getenv", 1, getenv),
+    ("exit", 1, exit),
+];
+
+/// The natives a VM knows by name. The VM looks here when a global is not defined
+#[derive(Default)]
+pub struct Natives {
+    table: HashMap<&'static str, Native>,
+}
+
+impl Natives {
+    /// The standard library every program gets
+    pub fn core() -> Self {
+        let mut natives = Self::default();
+        for &(name, arity, func) in CORE {
+            natives.register(Native { name, arity, func });
+        }
+        natives
+    }
+
+    /// Add a native, or replace the one with the same name
+    pub fn register(&mut self, native: Native) {
+        self.table.insert(native.name, native);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Native> {
+        self.table.get(name).copied()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.table.keys().copied()
+    }
+}
+
+fn string_arg<'a>(
+    args: &'a [Value],
+    idx: usize,
+    expected: &'static str,
+) -> Result<&'a str, NativeError> {
+    match &args[idx] {
+        Value::String(s) => Ok(s),
+        other => Err(NativeError::type_of(idx, expected, other)),
+    }
+}
+
+/// `clock()`, seconds since the program started
+fn clock(_out: &mut dyn Write, _args: &[Value]) -> NativeResult {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let start = START.get_or_init(Instant::now);
+    Ok(Value::Float(start.elapsed().as_secs_f32()))
+}
+
+/// `type(x)`, the name of the type of `x`
+fn type_(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    let name = match args[0] {
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::Int(_) | Value::Float(_) => "number",
+        Value::String(_) => "string",
+        Value::List(_) => "list",
+        Value::Native(_) => "function",
+    };
+    Ok(Value::String(name.to_string()))
+}
+
+/// `str(x)`, `x` the way `print` shows it
+fn str_(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    Ok(Value::String(args[0].to_string()))
+}
+
+/// `num(s)`, the number in a string, nil if it isn't one. Numbers are passed through
+fn num(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    match &args[0] {
+        Value::Int(_) | Value::Float(_) => Ok(args[0].clone()),
+        Value::String(s) => Ok(s.trim().parse().map_or(Value::Nil, Value::Float)),
+        other => Err(NativeError::type_of(0, "a string", other)),
+    }
+}
+
+/// `len(x)`, the length of a string or a list
+fn len(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    let len = match &args[0] {
+        Value::String(s) => s.chars().count(),
+        Value::List(items) => items.len(),
+        other => return Err(NativeError::type_of(0, "a string or a list", other)),
+    };
+    Ok(Value::Float(len as f32))
+}
+
+/// `input()`, the next line of stdin without the line break, nil at the end of the input
+fn input(out: &mut dyn Write, _args: &[Value]) -> NativeResult {
+    // a prompt printed right before has to show up before we wait
+    out.flush()
+        .map_err(|e| NativeError::Failed(e.to_string()))?;
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => {
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+            Ok(Value::String(line))
+        }
+        Err(e) => Err(NativeError::Failed(e.to_string())),
+    }
+}
+
+/// `getenv(name)`, the value of an environment variable or nil when it's not set
+fn getenv(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    let name = string_arg(args, 0, "a variable name")?;
+    Ok(std::env::var(name).map_or(Value::Nil, Value::String))
+}
+
+/// `exit(code)`, stops the program. The VM unwinds and `rs-lox` exits with `code`,
+/// which has to fit in a process exit status
+fn exit(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+    let out_of_range = |code: &dyn std::fmt::Display| {
+        NativeError::Failed(format!("exit code must be between 0 and 255, got {}", code))
+    };
+    match args[0] {
+        Value::Int(code) if (0..=255).contains(&code) => Err(NativeError::Exit(code)),
+        Value::Int(code) => Err(out_of_range(&code)),
+        Value::Float(code) if code.fract() != 0.0 => Err(NativeError::Failed(format!(
+            "exit code must be a whole number, got {}",
+            code
+        ))),
+        Value::Float(code) if (0.0..=255.0).contains(&code) => Err(NativeError::Exit(code as i32)),
+        Value::Float(code) => Err(out_of_range(&code)),
+        ref other => Err(NativeError::type_of(0, "a number", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::RuntimeError;
+    use crate::runtime::{Captured, Output};
+    use crate::session::RuntimeContext;
+
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let out = Captured::default();
+        let mut runtime = RuntimeContext::with_output(false, Output::new(out.clone(), io::sink()));
+        let addr = runtime.compile(source).unwrap();
+        runtime.exec(addr).map(|_| out.take())
+    }
+
+    #[test]
+    fn core_library() {
+        let source = r#"
+print type(nil) + " " + type(1) + " " + type("a") + " " + type(args) + " " + type(len);
+print str(1.5) + str(true);
+print num(" 42 ") + 1;
+print num("x");
+print len("abc") + len(args);
+print clock() >= 0;
+"#;
+        assert_eq!(
+            run(source).unwrap(),
+            "nil number string list function\n1.5true\n43\nNil\n3\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn argument_errors() {
+        let err = |source: &str| run(source).unwrap_err().to_string();
+        assert_eq!(err("len();"), "len() takes 1 arguments but got 0");
+        assert_eq!(err("clock(1);"), "clock() takes 0 arguments but got 1");
+        assert_eq!(
+            err("len(1);"),
+            "len(): argument 1 must be a string or a list, got Float(1.0)"
+        );
+        assert_eq!(
+            err("exit(1.5);"),
+            "exit(): exit code must be a whole number, got 1.5"
+        );
+        assert_eq!(
+            err("exit(256);"),
+            "exit(): exit code must be between 0 and 255, got 256"
+        );
+        assert_eq!(
+            err("exit(-1);"),
+            "exit(): exit code must be between 0 and 255, got -1"
+        );
+        assert_eq!(
+            err("exit(300.0);"),
+            "exit(): exit code must be between 0 and 255, got 300"
+        );
+        // a global of the same name wins
+        assert_eq!(run("var len = 1; print len;").unwrap(), "1\n");
+    }
+
+    #[test]
+    fn registry() {
+        fn twice(_out: &mut dyn Write, args: &[Value]) -> NativeResult {
+            Ok(args[0].add(args[0].clone()))
+        }
+        let mut natives = Natives::core();
+        assert!(natives.get("twice").is_none());
+        natives.register(Native {
+            name: "twice",
+            arity: 1,
+            func: twice,
+        });
+        let twice = natives.get("twice").unwrap();
+        let res = (twice.func)(&mut io::sink(), &[Value::Float(2.0)]).unwrap();
+        assert_eq!(res.to_string(), "4");
+        assert_eq!(natives.names().count(), CORE.len() + 1);
+    }
+}
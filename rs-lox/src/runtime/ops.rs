@@ -1,5 +1,10 @@
 use super::*;
 
+use std::io::Write;
+
+use values::NativeError;
+
+use super::strings;
 use super::utils::{stack_pop, stack_push};
 
 pub(super) fn exec_unary(op: OpCode, stack: &mut Stack) -> RTError<()> {
@@ -61,3 +66,64 @@ pub(super) fn exec_binary(op: OpCode, stack: &mut Stack) -> Result<(), RuntimeEr
         .map_err(|e| RuntimeError::StackError(format!("{}", e)))?;
     Ok(())
 }
+
+/// The callee sits below its `argc` arguments, both are replaced by the result.
+/// Natives get to write to `out`, the sink of `print`
+pub(super) fn exec_call(argc: u8, stack: &mut Stack, out: &mut dyn Write) -> RTError<()> {
+    let mut args = Vec::with_capacity(argc as usize);
+    for _ in 0..argc {
+        args.push(stack_pop(stack)?);
+    }
+    args.reverse();
+    let native = match stack_pop(stack)? {
+        Value::Native(native) => native,
+        other => return Err(RuntimeError::NotCallable(other)),
+    };
+    if args.len() != native.arity as usize {
+        return Err(RuntimeError::Arity(native.name, native.arity, args.len()));
+    }
+    let res = (native.func)(out, &args).map_err(|e| match e {
+        NativeError::Exit(code) => RuntimeError::Exit(code),
+        e => RuntimeError::Native(native.name, e),
+    })?;
+    stack_push(res, stack)
+}
+
+/// Like a call, but the method is looked up on the value below the arguments
+pub(super) fn exec_invoke(name: &str, argc: u8, stack: &mut Stack) -> RTError<()> {
+    let mut args = Vec::with_capacity(argc as usize);
+    for _ in 0..argc {
+        args.push(stack_pop(stack)?);
+    }
+    args.reverse();
+    let target = stack_pop(stack)?;
+    let (s, (name, arity, method)) = match (&target, strings::method(name)) {
+        (Value::String(s), Some(method)) => (s, method),
+        _ => return Err(RuntimeError::NoMethod(target, name.to_string())),
+    };
+    if args.len() != arity as usize {
+        return Err(RuntimeError::Arity(name, arity, args.len()));
+    }
+    let res = method(s, &args).map_err(|e| RuntimeError::Native(name, e))?;
+    stack_push(res, stack)
+}
+
+pub(super) fn exec_index(stack: &mut Stack) -> RTError<()> {
+    let index = stack_pop(stack)?;
+    let target = stack_pop(stack)?;
+    let val = match (&target, &index) {
+        (Value::List(items), Value::Float(idx)) if idx.fract() == 0.0 && *idx >= 0.0 => {
+            let idx = *idx as usize;
+            items
+                .get(idx)
+                .cloned()
+                .ok_or(RuntimeError::IndexOutOfRange(idx, items.len()))?
+        }
+        (Value::String(s), Value::Float(idx)) if idx.fract() == 0.0 && *idx >= 0.0 => {
+            let idx = *idx as usize;
+            strings::char_of(s, idx).ok_or(RuntimeError::IndexOutOfRange(idx, s.chars().count()))?
+        }
+        _ => return Err(RuntimeError::BadIndex(target, index)),
+    };
+    stack_push(val, stack)
+}
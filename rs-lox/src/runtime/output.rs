@@ -0,0 +1,104 @@
+//! Where a running program writes. `print` goes to the output sink, traces and error reports
+//! to the diagnostics sink, so a host embedding the VM can capture either one.
+use std::cell::RefCell;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+pub struct Output {
+    out: Box<dyn Write>,
+    diag: Box<dyn Write>,
+}
+
+impl Output {
+    pub fn new(out: impl Write + 'static, diag: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            diag: Box::new(diag),
+        }
+    }
+
+    /// stdout and stderr. stdout is buffered, the VM flushes it when a run returns
+    pub fn stdio() -> Self {
+        Self::new(BufWriter::new(io::stdout()), io::stderr())
+    }
+
+    /// What `print` writes to
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut self.out
+    }
+
+    /// Traces and error reports
+    pub fn diag(&mut self) -> &mut dyn Write {
+        &mut self.diag
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        self.diag.flush()
+    }
+}
+
+/// A sink that keeps what is written to it. Clones share the text,
+/// so one goes into the [`Output`] and the other is read after the run
+#[derive(Clone, Default)]
+pub struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Captured {
+    /// Everything written since the last call
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::RuntimeContext;
+
+    #[test]
+    fn sinks_are_separate() {
+        let (out, diag) = (Captured::default(), Captured::default());
+        // a buffered sink only shows up because the VM flushes when the run returns
+        let output = Output::new(BufWriter::new(out.clone()), diag.clone());
+        let mut runtime = RuntimeContext::with_output(false, output);
+        let addr = runtime
+            .compile("print 1;\nprint \"a\" + 2;\nprint nope;")
+            .unwrap();
+        let err = runtime.exec(addr).unwrap_err();
+        assert_eq!(out.take(), "1\na2\n");
+        assert_eq!(diag.take(), "");
+
+        runtime.report(&err, None);
+        assert_eq!(
+            diag.take(),
+            " Error: [ \n\t Unknown variable nope \n ]\n at line 3:7\n"
+        );
+
+        runtime.set_debug(true);
+        let addr = runtime.compile("print 2;").unwrap();
+        runtime.exec(addr).unwrap();
+        assert_eq!(out.take(), "2\n");
+        assert!(diag.take().contains("PRINT <- line 1"));
+
+        // the dumps of a failed debug run and the stack report stay off `out` too
+        let addr = runtime.compile("print nope;").unwrap();
+        runtime.exec(addr).unwrap_err();
+        runtime.debug_report();
+        let dumped = diag.take();
+        assert!(dumped.contains("===== Globals ======"), "{}", dumped);
+        assert!(dumped.ends_with(" []\n"), "{}", dumped);
+        assert_eq!(out.take(), "");
+    }
+}
@@ -0,0 +1,238 @@
+//! Methods of string values, `"a,b".split(",")`. Indices count characters and
+//! ranges leave out their end, like rust's.
+use values::{NativeError, Value};
+
+type Method = fn(&str, &[Value]) -> Result<Value, NativeError>;
+
+/// Longest string `repeat` makes, in bytes
+const MAX_LEN: usize = 1 << 28;
+/// Past 2^24 floats skip whole numbers, nothing sensible is that big anyway
+const MAX_COUNT: f32 = 16_777_216.0;
+
+const METHODS: &[(&str, u8, Method)] = &[
+    ("substr", 2, substr),
+    ("char_at", 1, char_at),
+    ("index_of", 1, index_of),
+    ("contains", 1, contains),
+    ("starts_with", 1, starts_with),
+    ("ends_with", 1, ends_with),
+    ("upper", 0, upper),
+    ("lower", 0, lower),
+    ("trim", 0, trim),
+    ("split", 1, split),
+    ("join", 1, join),
+    ("replace", 2, replace),
+    ("repeat", 1, repeat),
+];
+
+/// The method called `name` with its arity, the receiver doesn't count
+pub(super) fn method(name: &str) -> Option<(&'static str, u8, Method)> {
+    METHODS.iter().find(|(n, _, _)| *n == name).copied()
+}
+
+/// The character at `index`, for `s[index]`
+pub(super) fn char_of(s: &str, index: usize) -> Option<Value> {
+    s.chars().nth(index).map(|c| Value::String(c.to_string()))
+}
+
+fn str_arg(args: &[Value], idx: usize) -> Result<&str, NativeError> {
+    match &args[idx] {
+        Value::String(s) => Ok(s),
+        other => Err(NativeError::type_of(idx, "a string", other)),
+    }
+}
+
+fn count_arg(args: &[Value], idx: usize, expected: &'static str) -> Result<usize, NativeError> {
+    match args[idx] {
+        Value::Int(n) if n >= 0 => Ok(n as usize),
+        Value::Float(n) if n >= 0.0 && n.fract() == 0.0 && n <= MAX_COUNT => Ok(n as usize),
+        Value::Float(n) if n >= 0.0 && n.fract() == 0.0 => Err(NativeError::Failed(format!(
+            "argument {} is too large for {}, the most is {}, got {}",
+            idx + 1,
+            expected,
+            MAX_COUNT,
+            n
+        ))),
+        ref other => Err(NativeError::type_of(idx, expected, other)),
+    }
+}
+
+/// Byte offset of the character at `index`, the length of `s` is a valid end
+fn offset(s: &str, index: usize) -> Result<usize, NativeError> {
+    s.char_indices()
+        .map(|(offset, _)| offset)
+        .chain([s.len()])
+        .nth(index)
+        .ok_or(NativeError::OutOfRange {
+            index,
+            len: s.chars().count(),
+        })
+}
+
+fn string(s: impl Into<String>) -> Result<Value, NativeError> {
+    Ok(Value::String(s.into()))
+}
+
+/// `s.substr(start, end)`
+fn substr(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let start = count_arg(args, 0, "an index")?;
+    let end = count_arg(args, 1, "an index")?;
+    let (from, to) = (offset(s, start)?, offset(s, end)?);
+    if from > to {
+        return Err(NativeError::Failed(format!(
+            "start {} is after end {}",
+            start, end
+        )));
+    }
+    string(&s[from..to])
+}
+
+/// `s.char_at(index)`, same as `s[index]`
+fn char_at(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let index = count_arg(args, 0, "an index")?;
+    char_of(s, index).ok_or(NativeError::OutOfRange {
+        index,
+        len: s.chars().count(),
+    })
+}
+
+/// `s.index_of(sub)`, where `sub` first shows up or nil
+fn index_of(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let sub = str_arg(args, 0)?;
+    Ok(s.find(sub).map_or(Value::Nil, |offset| {
+        Value::Float(s[..offset].chars().count() as f32)
+    }))
+}
+
+fn contains(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::Bool(s.contains(str_arg(args, 0)?)))
+}
+
+fn starts_with(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::Bool(s.starts_with(str_arg(args, 0)?)))
+}
+
+fn ends_with(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::Bool(s.ends_with(str_arg(args, 0)?)))
+}
+
+fn upper(s: &str, _args: &[Value]) -> Result<Value, NativeError> {
+    string(s.to_uppercase())
+}
+
+fn lower(s: &str, _args: &[Value]) -> Result<Value, NativeError> {
+    string(s.to_lowercase())
+}
+
+fn trim(s: &str, _args: &[Value]) -> Result<Value, NativeError> {
+    string(s.trim())
+}
+
+/// `s.split(sep)`, a list of the parts. An empty `sep` splits into characters
+fn split(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let sep = str_arg(args, 0)?;
+    let parts: Vec<Value> = if sep.is_empty() {
+        s.chars().map(|c| Value::String(c.to_string())).collect()
+    } else {
+        s.split(sep).map(|p| Value::String(p.to_string())).collect()
+    };
+    Ok(Value::List(parts))
+}
+
+/// `sep.join(list)`, the items the way `print` shows them with `sep` between them
+fn join(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(items) = &args[0] else {
+        return Err(NativeError::type_of(0, "a list", &args[0]));
+    };
+    let parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+    string(parts.join(s))
+}
+
+/// `s.replace(from, to)`, every `from` becomes `to`
+fn replace(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    string(s.replace(str_arg(args, 0)?, str_arg(args, 1)?))
+}
+
+fn repeat(s: &str, args: &[Value]) -> Result<Value, NativeError> {
+    let count = count_arg(args, 0, "a count")?;
+    match s.len().checked_mul(count) {
+        Some(len) if len <= MAX_LEN => string(s.repeat(count)),
+        _ => Err(NativeError::Failed(format!(
+            "the result would be longer than {} bytes",
+            MAX_LEN
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::errors::RuntimeError;
+    use crate::runtime::{Captured, Output};
+    use crate::session::RuntimeContext;
+
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let out = Captured::default();
+        let mut runtime = RuntimeContext::with_output(false, Output::new(out.clone(), io::sink()));
+        let addr = runtime.compile(source).unwrap();
+        runtime.exec(addr).map(|_| out.take())
+    }
+
+    #[test]
+    fn methods() {
+        let source = r#"
+var line = "  GET /index.html 200  ".trim();
+print line.substr(4, 15) + "|" + line[0] + line.char_at(1);
+print line.index_of("200");
+print line.index_of("404");
+print line.contains("index") and line.starts_with("GET") and line.ends_with("200");
+print line.lower().upper();
+print line.split(" ");
+print "-".join("a,b,c".split(",")) + "-".join("ab".split(""));
+print "a.b".replace(".", "::").repeat(2);
+"#;
+        assert_eq!(
+            run(source).unwrap(),
+            "/index.html|GE\n16\nNil\ntrue\nGET /INDEX.HTML 200\n[GET, /index.html, 200]\na-b-ca-b\na::ba::b\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = |source: &str| run(source).unwrap_err().to_string();
+        assert_eq!(
+            err(r#""abc".substr(1, 4);"#),
+            "substr(): index 4 is out of range, the length is 3"
+        );
+        assert_eq!(
+            err(r#""abc".substr(2, 1);"#),
+            "substr(): start 2 is after end 1"
+        );
+        assert_eq!(
+            err(r#""abc"[3];"#),
+            "Index 3 is out of range, the length is 3"
+        );
+        assert_eq!(
+            err(r#""abc".repeat(-1);"#),
+            "repeat(): argument 1 must be a count, got Float(-1.0)"
+        );
+        assert_eq!(
+            err(r#""ab".repeat(99999999999);"#),
+            "repeat(): argument 1 is too large for a count, the most is 16777216, got 100000000000"
+        );
+        assert_eq!(
+            err(r#""ab".repeat(16000000).repeat(16);"#),
+            "repeat(): the result would be longer than 268435456 bytes"
+        );
+        assert_eq!(
+            err(r#""abc".trim(1);"#),
+            "trim() takes 0 arguments but got 1"
+        );
+        assert_eq!(
+            err(r#""abc".nope();"#),
+            "String(\"abc\") has no method nope()"
+        );
+        assert_eq!(err("(1).upper();"), "Float(1.0) has no method upper()");
+    }
+}
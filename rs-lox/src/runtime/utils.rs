@@ -1,17 +1,11 @@
 use super::*;
 
+use std::io;
+
 pub(super) fn stack_pop(stack: &mut Stack) -> RTError<Value> {
-    let res = stack
+    stack
         .pop()
-        .map_err(|e| RuntimeError::StackError(format!("{}", e)));
-    match res {
-        Err(e) => {
-            stack.show_stack();
-            println!("Got in trouble for {}", e);
-            Err(e)
-        }
-        Ok(v) => Ok(v),
-    }
+        .map_err(|e| RuntimeError::StackError(format!("{}", e)))
 }
 
 pub(super) fn stack_push(val: Value, stack: &mut Stack) -> RTError<()> {
@@ -24,6 +18,7 @@ impl VM {
     pub fn load_chunk(&mut self, chunk: Chunk) {
         self.ip = 0;
         self.chunk = Some(chunk);
+        self.verified = false;
     }
 
     pub fn unload_chunk(&mut self) -> Chunk {
@@ -38,49 +33,61 @@ impl VM {
             .expect("Runtime Exception: Called run on an empty chunk")
     }
 
-    pub(super) fn read_byte(&self) -> OpCode {
-        // this can't be called outside of run, wehre we make sure that chunk is not empty
-        if self.ip >= self.cur_chunk().count() {
-            self.cur_chunk().debug_ops_dump();
-            print!("\t STACK: ");
-            self.show_stack();
-        }
-        let op = *self.cur_chunk().read_op(self.ip).expect(&format!(
-            " IP Out of Bounds: Failed to read Op from {} instruction pointer",
-            self.ip
-        ));
-        if self.debug {
-            // disassemble_op(self.cur_chunk(), self.ip);
-        }
-        op
+    /// Decode the op at ip, returns it with its size
+    #[inline]
+    pub(super) fn read_byte(&self) -> (OpCode, usize) {
+        // only called from run, after the chunk was verified. so ip is always the start of an op,
+        // jumps land on one and the code ends with a RETURN
+        self.cur_chunk().fetch_op(self.ip)
     }
 
-    pub(super) fn push(&mut self, val: Value) {
-        self.stack.borrow_mut().push(val).expect("Stack push error");
+    pub(super) fn push(&mut self, val: Value) -> RTError<()> {
+        stack_push(val, &mut self.stack.borrow_mut())
     }
 
-    pub(super) fn pop(&mut self) -> Value {
-        match self.stack.borrow_mut().pop() {
-            Ok(v) => v,
-            Err(e) => {
-                println!(" [ Error -> @{:04}:{} ]", self.ip, e);
-                panic!()
-            }
-        }
+    pub(super) fn pop(&mut self) -> RTError<Value> {
+        stack_pop(&mut self.stack.borrow_mut())
     }
 
-    pub(super) fn peek(&self) -> RTError<Value> {
+    pub fn peek(&self) -> RTError<Value> {
         self.stack
             .borrow()
             .peek()
-            .map(|v| v.clone())
+            .cloned()
             .ok_or(RuntimeError::StackError(
                 "Peeked on an empty staclk".to_string(),
             ))
     }
 
-    pub fn show_stack(&self) {
-        self.stack.borrow().show_stack();
+    pub fn stack_height(&self) -> usize {
+        self.stack.borrow().len()
+    }
+
+    /// Drop the values above `height`
+    pub fn truncate_stack(&mut self, height: usize) {
+        self.stack.borrow_mut().truncate(height);
+    }
+
+    /// Throw away what a failed run left behind: the values it pushed above `height`
+    /// (temporaries and the locals of the blocks it was in) and its place in the chunk.
+    /// Globals it already defined stay, like they would if a script stopped there
+    pub fn recover(&mut self, height: usize) {
+        self.truncate_stack(height);
+        self.ip = 0;
+        self.verified = false;
+    }
+
+    /// Everything on the stack, bottom first
+    pub fn stack_values(&self) -> Vec<Value> {
+        let stack = self.stack.borrow();
+        (0..stack.len())
+            .filter_map(|i| stack.get(i).cloned())
+            .collect()
+    }
+
+    /// Print the stack to the diagnostics sink
+    pub fn show_stack(&mut self) -> io::Result<()> {
+        self.stack.borrow().show_stack(self.output.diag())
     }
 
     pub(super) fn _read_ident(&self, ident_: ConstIdx) -> String {
@@ -93,14 +100,15 @@ impl VM {
         key
     }
 
-    pub(super) fn debug_dump(&mut self) {
-        if let Some(chunk) = self.chunk.clone() {
-            println!(" ===== Constants =====");
-            for cons in chunk.consts {
-                println!(" -> {}", cons);
+    pub(super) fn debug_dump(&mut self) -> io::Result<()> {
+        let diag = self.output.diag();
+        if let Some(chunk) = self.chunk.as_ref() {
+            writeln!(diag, " ===== Constants =====")?;
+            for cons in chunk.consts.iter() {
+                writeln!(diag, " -> {}", cons)?;
             }
         };
-        println!("===== Globals ======");
-        println!(" -> {:?}", self.globals);
+        writeln!(diag, "===== Globals ======")?;
+        writeln!(diag, " -> {:?}", self.globals)
     }
 }
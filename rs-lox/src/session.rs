@@ -1,43 +1,74 @@
-use crate::errors::RTError;
+use crate::errors::{format_runtime_error, COMPError, RTError, RuntimeError};
 
-use crate::runtime::VM;
-use compiler::COMPError;
-use compiler::Parser;
+use crate::runtime::{Hook, Output, VM};
+use compiler::ast::{Expr, Program};
+use compiler::{CodeGen, Parser};
 use lang::Scanner;
-use values::Chunk;
+use values::{Chunk, SourcePos, Value};
 pub type ChunkAddr = usize;
 
 pub struct RuntimeContext {
     vm: VM,
     chunks: Vec<Option<Chunk>>,
     debug: bool,
+    /// where the last run failed
+    fault: Option<SourcePos>,
 }
 
 impl RuntimeContext {
+    /// Runtime that prints to stdout and reports to stderr
     pub fn start(debug: bool) -> Self {
+        Self::with_output(debug, Output::stdio())
+    }
+
+    pub fn with_output(debug: bool, output: Output) -> Self {
         // let heap = RefCell::new(Heap::init());
-        let vm = VM::init(debug);
+        let vm = VM::init(debug, output);
         Self {
             vm,
             chunks: vec![],
             debug,
+            fault: None,
         }
     }
 
+    pub fn parse(&self, source: &str) -> COMPError<Program> {
+        let mut scanner = Scanner::from_str(source)?;
+        let mut parser = Parser::init(&mut scanner);
+        parser.parse()
+    }
+
     pub fn compile(&mut self, source: &str) -> COMPError<ChunkAddr> {
-        let mut scanner = Scanner::from_str(source).unwrap();
+        let program = self.parse(source)?;
+        self.compile_program(&program)
+    }
+
+    pub fn compile_program(&mut self, program: &Program) -> COMPError<ChunkAddr> {
         let mut chunk = Chunk::new();
 
-        // let mut parser = Parser::init(&mut scanner, &mut chunk, self.heap.borrow_mut());
-        let mut parser = Parser::init(&mut scanner, &mut chunk);
-        let pres = parser.parse();
-        if pres.is_err() && self.debug {
-            chunk.debug_ops_dump();
-            return Err(pres.err().unwrap());
+        let mut codegen = CodeGen::init(&mut chunk);
+        if let Err(e) = codegen.generate(program) {
+            if self.debug {
+                let dump = chunk.debug_ops_dump();
+                let _ = self.vm.output().diag().write_all(dump.as_bytes());
+            }
+            return Err(e);
         }
 
+        Ok(self.load(chunk))
+    }
+
+    /// A single expression, running the chunk leaves its value on the stack for [`RuntimeContext::exec_expr`]
+    pub fn compile_expr(&mut self, expr: &Expr) -> COMPError<ChunkAddr> {
+        let mut chunk = Chunk::new();
+        CodeGen::init(&mut chunk).generate_expr(expr)?;
+        Ok(self.load(chunk))
+    }
+
+    /// Take a chunk that was compiled elsewhere, like a `.loxc` file
+    pub fn load(&mut self, chunk: Chunk) -> ChunkAddr {
         self.chunks.push(Some(chunk));
-        Ok(self.chunks.len() - 1)
+        self.chunks.len() - 1
     }
 
     pub fn get_chunk(&mut self, addr: ChunkAddr) -> Chunk {
@@ -48,17 +79,82 @@ impl RuntimeContext {
         self.chunks[addr] = Some(chunk);
     }
 
+    /// Run a chunk. After a runtime error the VM is back where it was before the run,
+    /// except for the globals that were already set
     pub fn exec(&mut self, addr: ChunkAddr) -> RTError<()> {
         let cur_chunk = self.get_chunk(addr);
+        let height = self.vm.stack_height();
         self.vm.load_chunk(cur_chunk);
         let res = self.vm.run();
+        self.fault = res.as_ref().err().and_then(|_| self.vm.pos());
+        if res.is_err() {
+            self.vm.recover(height);
+        }
         // make sure to put the chunk back even if the execution fails
         let cur_chunk = self.vm.unload_chunk();
         self.put_chunk(addr, cur_chunk);
         res
     }
 
-    pub fn debug_report(&self) {
-        self.vm.show_stack();
+    /// Run a chunk from [`RuntimeContext::compile_expr`] and return the value of the expression
+    pub fn exec_expr(&mut self, addr: ChunkAddr) -> RTError<Value> {
+        let height = self.vm.stack_height();
+        let res = self.exec(addr).and_then(|_| self.vm.peek());
+        self.vm.truncate_stack(height);
+        res
+    }
+
+    /// Like [`RuntimeContext::exec`], with a hook watching every op. Pausing is not supported here
+    pub fn exec_hooked(&mut self, addr: ChunkAddr, hook: &mut dyn Hook) -> RTError<()> {
+        let cur_chunk = self.get_chunk(addr);
+        let height = self.vm.stack_height();
+        self.vm.load_chunk(cur_chunk);
+        let res = self.vm.run_hooked(Some(hook)).map(|_| ());
+        self.fault = res.as_ref().err().and_then(|_| self.vm.pos());
+        if res.is_err() {
+            self.vm.recover(height);
+        }
+        let cur_chunk = self.vm.unload_chunk();
+        self.put_chunk(addr, cur_chunk);
+        res
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+        self.vm.set_debug(debug);
+    }
+
+    /// What the program sees as `args`
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.vm.set_args(args);
+    }
+
+    pub fn output(&mut self) -> &mut Output {
+        self.vm.output()
+    }
+
+    /// Swap the sinks, the old ones are returned
+    pub fn set_output(&mut self, output: Output) -> Output {
+        self.vm.set_output(output)
+    }
+
+    /// Write a runtime error of the last run to the diagnostics sink
+    pub fn report(&mut self, e: &RuntimeError, source: Option<&str>) {
+        let report = format_runtime_error(e, self.fault, source);
+        let diag = self.vm.output().diag();
+        // there is nowhere left to complain to if this fails
+        let _ = diag.write_all(report.as_bytes()).and_then(|_| diag.flush());
+    }
+
+    pub fn debug_report(&mut self) {
+        // like `report`, there is nowhere left to complain to
+        let _ = self
+            .vm
+            .show_stack()
+            .and_then(|_| self.vm.output().diag().flush());
     }
 }
@@ -0,0 +1,36 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Expand the command line paths into `.lox` files. Directories are walked recursively,
+/// skipping hidden ones and cargo's `target`
+pub fn collect_lox_files(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut out = vec![];
+    for p in paths {
+        let path = Path::new(p);
+        if path.is_dir() {
+            walk(path, &mut out)?;
+        } else {
+            out.push(path.to_path_buf());
+        }
+    }
+    Ok(out)
+}
+
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.path());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                walk(&path, out)?;
+            }
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
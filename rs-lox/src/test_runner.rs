@@ -0,0 +1,395 @@
+//! `rs-lox test [paths..]` runs `.lox` scripts and checks them against the annotations in their comments,
+//! in the style of the Crafting Interpreters test suite:
+//!
+//! - `// expect: text` a line the script prints, in order
+//! - `// expect runtime error: message` the script fails with this runtime error
+//! - `// error at line N` the script doesn't compile, the error is reported on line N
+//!
+//! `test "name" { .. }` blocks are run too, each one on a fresh VM after the top level code of the file.
+//! A failed `assert`/`assert_eq` (or any other runtime error) fails the test.
+//!
+//! Files without annotations or tests are skipped. The command exits with 1 if anything failed.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use compiler::ast::{Program, Stmt, StmtKind};
+use lang::{line_col, Scanner, TokenType};
+
+use crate::cli::{EXIT_FAILURE, EXIT_IO};
+use crate::runtime::{Captured, Output};
+use crate::session::RuntimeContext;
+use crate::sources::collect_lox_files;
+
+const EXPECT: &str = "expect:";
+const EXPECT_RUNTIME: &str = "expect runtime error:";
+const ERROR_AT: &str = "error at line";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub runtime_error: Option<String>,
+    /// lines a compile error may be reported on
+    pub compile_error: Vec<usize>,
+}
+
+impl Expectations {
+    /// Read the annotations out of the comments. Only real comments count, not text in strings
+    pub fn parse(source: &str) -> Self {
+        let mut res = Expectations::default();
+        let Ok(mut scanner) = Scanner::lossless(source) else {
+            return res;
+        };
+        loop {
+            let tok = match scanner.scan_token() {
+                Ok(tok) => tok,
+                Err(_) => continue,
+            };
+            match tok.ty {
+                TokenType::EoF => break,
+                TokenType::Comment => {}
+                _ => continue,
+            }
+            let text = scanner.lexeme().trim_start_matches('/').trim();
+            if let Some(out) = text.strip_prefix(EXPECT) {
+                res.output.push(out.trim().to_string());
+            } else if let Some(msg) = text.strip_prefix(EXPECT_RUNTIME) {
+                res.runtime_error = Some(msg.trim().to_string());
+            } else if let Some(line) = text.strip_prefix(ERROR_AT) {
+                if let Ok(line) = line.trim().parse() {
+                    res.compile_error.push(line);
+                }
+            }
+        }
+        res
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Expectations::default()
+    }
+}
+
+/// A runtime that keeps what the script prints instead of writing it out
+fn capturing_runtime() -> (RuntimeContext, Captured) {
+    let out = Captured::default();
+    let runtime = RuntimeContext::with_output(false, Output::new(out.clone(), io::sink()));
+    (runtime, out)
+}
+
+/// Run the script and compare. `Err` holds the explanation of what went wrong
+pub fn check(source: &str, expected: &Expectations) -> Result<(), String> {
+    let (mut runtime, out) = capturing_runtime();
+    let addr = match runtime.compile(source) {
+        Ok(addr) => addr,
+        Err(e) => {
+            let line = e.span().map(|s| line_col(source, s.start).0 as usize);
+            return match line {
+                Some(line) if expected.compile_error.contains(&line) => Ok(()),
+                Some(line) => Err(format!(
+                    "unexpected compile error on line {}: {}",
+                    line,
+                    e.message()
+                )),
+                None => Err(format!("unexpected compile error: {}", e.message())),
+            };
+        }
+    };
+    if !expected.compile_error.is_empty() {
+        let lines: Vec<String> = expected
+            .compile_error
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        return Err(format!(
+            "expected a compile error at line {}, but it compiled",
+            lines.join(" or ")
+        ));
+    }
+
+    let res = runtime.exec(addr);
+    let output: Vec<String> = out.take().lines().map(String::from).collect();
+    let mut problems = vec![];
+    if output != expected.output {
+        problems.push(diff(&expected.output, &output));
+    }
+    match (res, &expected.runtime_error) {
+        (Ok(_), None) => {}
+        (Ok(_), Some(msg)) => {
+            problems.push(format!("expected runtime error `{}`, but it ran fine", msg))
+        }
+        (Err(e), None) => problems.push(format!("unexpected runtime error: {}", e)),
+        (Err(e), Some(msg)) if e.to_string() != *msg => {
+            problems.push(format!("expected runtime error `{}`, got `{}`", msg, e))
+        }
+        (Err(_), Some(_)) => {}
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("\n"))
+    }
+}
+
+/// Run every `test` block of the program, in order. The top level code runs before each test,
+/// other tests are left out
+pub fn run_tests(program: &Program) -> Vec<(String, Result<(), String>)> {
+    let setup: Vec<Stmt> = program
+        .decls
+        .iter()
+        .filter(|d| !matches!(d.kind, StmtKind::Test { .. }))
+        .cloned()
+        .collect();
+    program
+        .decls
+        .iter()
+        .filter_map(|d| match &d.kind {
+            StmtKind::Test { name, body } => Some((name.clone(), body)),
+            _ => None,
+        })
+        .map(|(name, body)| {
+            let mut decls = setup.clone();
+            decls.push(body.as_ref().clone());
+            (name, run_test(&Program { decls }))
+        })
+        .collect()
+}
+
+fn run_test(program: &Program) -> Result<(), String> {
+    // tests don't get to print in between the results
+    let (mut runtime, _) = capturing_runtime();
+    let addr = runtime.compile_program(program).map_err(|e| e.message())?;
+    runtime.exec(addr).map_err(|e| e.to_string())
+}
+
+/// Line by line, `-` is expected and `+` is what we got
+fn diff(expected: &[String], actual: &[String]) -> String {
+    let mut out = vec!["output differs:".to_string()];
+    for idx in 0..expected.len().max(actual.len()) {
+        match (expected.get(idx), actual.get(idx)) {
+            (Some(e), Some(a)) if e == a => out.push(format!("    {}", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push(format!("  - {}", e));
+                }
+                if let Some(a) = a {
+                    out.push(format!("  + {}", a));
+                }
+            }
+        }
+    }
+    out.join("\n")
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let mut paths = args.to_vec();
+    if paths.is_empty() {
+        paths.push(".".to_string());
+    }
+    let files = match collect_lox_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("test: {}", e);
+            return EXIT_IO;
+        }
+    };
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for path in files {
+        let results = run_file(&path);
+        if results.is_empty() {
+            skipped += 1;
+        }
+        for (name, res) in results {
+            let label = match name {
+                Some(name) => format!("{}::{}", path.display(), name),
+                None => path.display().to_string(),
+            };
+            match res {
+                Ok(_) => {
+                    println!("PASS {}", label);
+                    passed += 1;
+                }
+                Err(why) => {
+                    println!("FAIL {}", label);
+                    for l in why.lines() {
+                        println!("  {}", l);
+                    }
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        passed, failed, skipped
+    );
+    if failed > 0 {
+        EXIT_FAILURE
+    } else {
+        0
+    }
+}
+
+/// Results of the annotations check (no name) and of every test in the file.
+/// Empty if the file has nothing to check
+fn run_file(path: &Path) -> Vec<(Option<String>, Result<(), String>)> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return vec![(None, Err(e.to_string()))],
+    };
+    let mut results = vec![];
+    let expected = Expectations::parse(&source);
+    if !expected.is_empty() {
+        results.push((None, check(&source, &expected)));
+    }
+    match RuntimeContext::start(false).parse(&source) {
+        Ok(program) => {
+            for (name, res) in run_tests(&program) {
+                results.push((Some(name), res));
+            }
+        }
+        // a broken file with annotations is already covered by the check
+        Err(e) if expected.is_empty() && declares_tests(&source) => {
+            let why = match e.span() {
+                Some(span) => format!(
+                    "compile error on line {}: {}",
+                    line_col(&source, span.start).0,
+                    e.message()
+                ),
+                None => format!("compile error: {}", e.message()),
+            };
+            results.push((None, Err(why)));
+        }
+        Err(_) => {}
+    }
+    results
+}
+
+fn declares_tests(source: &str) -> bool {
+    let Ok(mut scanner) = Scanner::from_str(source) else {
+        return false;
+    };
+    loop {
+        match scanner.scan_token() {
+            Ok(tok) if tok.ty == TokenType::Test => return true,
+            Ok(tok) if tok.ty == TokenType::EoF => return false,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> Result<(), String> {
+        let expected = Expectations::parse(source);
+        assert!(!expected.is_empty());
+        check(source, &expected)
+    }
+
+    #[test]
+    fn annotations() {
+        let source = "print \"// expect: no\"; // expect: // expect: no\n// expect runtime error: boom\n//   error at line 3\n";
+        let expected = Expectations::parse(source);
+        assert_eq!(expected.output, ["// expect: no"]);
+        assert_eq!(expected.runtime_error.as_deref(), Some("boom"));
+        assert_eq!(expected.compile_error, [3]);
+    }
+
+    #[test]
+    fn passing_scripts() {
+        assert_eq!(
+            run("print 1 + 2; // expect: 3\nprint \"a\"; // expect: a\n"),
+            Ok(())
+        );
+        assert_eq!(
+            run("print 1; // expect: 1\nprint x; // expect runtime error: Unknown variable x\n"),
+            Ok(())
+        );
+        assert_eq!(run("var a = 1;\nprint ; // error at line 2\n"), Ok(()));
+    }
+
+    #[test]
+    fn failing_scripts() {
+        let err = run("print 1; // expect: 1\nprint 3; // expect: 2\n// expect: 4\n").unwrap_err();
+        assert_eq!(err, "output differs:\n    1\n  - 2\n  + 3\n  - 4");
+
+        let err = run("print x; // expect: 1\n").unwrap_err();
+        assert!(
+            err.ends_with("unexpected runtime error: Unknown variable x"),
+            "{}",
+            err
+        );
+
+        let err = run("print 1; // error at line 1\n").unwrap_err();
+        assert_eq!(err, "expected a compile error at line 1, but it compiled");
+
+        let err = run("print 1;\nprint ; // error at line 1\n").unwrap_err();
+        assert_eq!(err, "unexpected compile error on line 2: Bad Expression");
+    }
+
+    #[test]
+    fn repo_scripts_pass() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for name in ["all_tests.lox", "test.lox"] {
+            let res = run_file(&root.join(name));
+            assert_eq!(res, [(None, Ok(()))], "{}", name);
+        }
+    }
+
+    fn tests_of(source: &str) -> Vec<(String, Result<(), String>)> {
+        let program = RuntimeContext::start(false).parse(source).unwrap();
+        run_tests(&program)
+    }
+
+    #[test]
+    fn test_blocks() {
+        let source = "var a = 1;
+test \"math\" {
+    var b = a + 1;
+    assert(b > a);
+    assert_eq(b * 2, 4);
+}
+test \"globals are fresh\" {
+    a = a + 1;
+    assert_eq(a, 2);
+}
+test \"fails\" {
+    assert_eq(a + 1, 3);
+}
+test \"falsy\" {
+    print \"hidden\";
+    assert(nil);
+}
+print a;
+";
+        let results = tests_of(source);
+        let names: Vec<&str> = results.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["math", "globals are fresh", "fails", "falsy"]);
+        assert_eq!(results[0].1, Ok(()));
+        assert_eq!(results[1].1, Ok(()));
+        assert_eq!(
+            results[2].1,
+            Err("line 12: assert_eq failed\n  left: 2\n right: 3".to_string())
+        );
+        assert_eq!(
+            results[3].1,
+            Err("line 16: assertion failed, got Nil".to_string())
+        );
+
+        // normal runs skip the tests
+        let (mut runtime, out) = capturing_runtime();
+        let addr = runtime.compile(source).unwrap();
+        runtime.exec(addr).unwrap();
+        assert_eq!(out.take(), "1\n");
+    }
+
+    #[test]
+    fn tests_only_at_the_top_level() {
+        let err = RuntimeContext::start(false)
+            .parse("{ test \"inner\" { } }")
+            .unwrap_err();
+        assert_eq!(err.message(), "Tests can only be declared at the top level");
+    }
+}
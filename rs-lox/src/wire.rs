@@ -0,0 +1,41 @@
+//! `Content-Length` framed JSON messages, the transport of both LSP and DAP.
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value as Json;
+
+/// Read the next message. `None` once the other side closed the stream
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut len: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let len = len.ok_or_else(|| bad_data("missing Content-Length header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| bad_data(&e.to_string()))
+}
+
+pub fn write_message(out: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+fn bad_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -1,13 +1,26 @@
 use lang::{ConstIdx, OpCode};
 
-use crate::Value;
+use crate::{SourceMap, SourcePos, Value};
 
+/// Bytecode of a program. Ops are stored encoded (see [`OpCode::encode`]), an instruction address
+/// is the offset of its first byte
 #[derive(Debug, Clone)]
 pub struct Chunk {
-    ops: Vec<OpCode>,
+    pub(crate) code: Vec<u8>,
     pub consts: Vec<Value>,
-    /// source code line that got the opcode from
-    pub line_nums: Vec<usize>,
+    /// where in the source every byte of code came from
+    pub source_map: SourceMap,
+    /// debug info, where every local lives on the stack
+    pub locals: Vec<DebugLocal>,
+}
+
+/// A local variable is in stack `slot` while `start <= ip < end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugLocal {
+    pub name: String,
+    pub slot: u8,
+    pub start: usize,
+    pub end: usize,
 }
 
 #[inline]
@@ -15,24 +28,38 @@ fn op_comp(a: OpCode, b: OpCode) -> bool {
     std::mem::discriminant(&a) == std::mem::discriminant(&b)
 }
 
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chunk {
     pub fn new() -> Self {
         Self {
-            ops: vec![],
+            code: vec![],
             consts: vec![],
-            line_nums: vec![],
+            source_map: SourceMap::default(),
+            locals: vec![],
         }
     }
 
+    /// Size of the code in bytes, which is also the address of the next op
     pub fn count(&self) -> usize {
-        self.ops.len()
+        self.code.len()
     }
 
     /// This adds an OpCode to our code chunk
     /// unlike in C, we don't need to handle the growth and size counter for a vec. its already a part of std::Vec
     pub fn add_op(&mut self, op: OpCode, line: usize) {
-        self.ops.push(op);
-        self.line_nums.push(line);
+        self.add_op_at(op, SourcePos::line(line as u32));
+    }
+
+    /// Add an op that was compiled from the expression at `pos`
+    pub fn add_op_at(&mut self, op: OpCode, pos: SourcePos) {
+        let start = self.code.len();
+        op.encode(&mut self.code);
+        self.source_map.push(self.code.len() - start, pos);
     }
 
     /// Returns the offset to the constant array of the latest constant added
@@ -41,8 +68,31 @@ impl Chunk {
         self.consts.len() - 1
     }
 
-    pub fn read_op(&self, ip: usize) -> Option<&OpCode> {
-        self.ops.get(ip)
+    pub fn read_op(&self, ip: usize) -> Option<OpCode> {
+        self.decode_op(ip).map(|(op, _)| op)
+    }
+
+    /// The op at `ip` and its size in bytes
+    #[inline(always)]
+    pub fn decode_op(&self, ip: usize) -> Option<(OpCode, usize)> {
+        OpCode::decode(self.code.get(ip..)?)
+    }
+
+    /// [`Chunk::decode_op`] without the checks, for chunks that passed [`Chunk::verify`].
+    /// `ip` must be the start of an op
+    #[inline(always)]
+    pub fn fetch_op(&self, ip: usize) -> (OpCode, usize) {
+        OpCode::decode_verified(&self.code[ip..])
+    }
+
+    /// Every op with its address, in order
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, OpCode)> + '_ {
+        let mut ip = 0;
+        std::iter::from_fn(move || {
+            let (op, len) = self.decode_op(ip)?;
+            ip += len;
+            Some((ip - len, op))
+        })
     }
 
     pub fn read_const(&self, addr: ConstIdx) -> &Value {
@@ -51,15 +101,18 @@ impl Chunk {
 
     pub fn patch_op(&mut self, op: OpCode, ip: usize) {
         if !self.ops_match(op, ip) {
-            let top = self.ops[ip];
+            let top = self.read_op(ip);
             let msg = format!("Trying to patch unmatchin ops {:?}, {:?}", op, top);
             panic!("{}", &msg);
         }
-        self.ops[ip] = op;
+        // same op, so the encoding has the same size
+        let mut bytes = vec![];
+        op.encode(&mut bytes);
+        self.code[ip..ip + bytes.len()].copy_from_slice(&bytes);
     }
 
     fn ops_match(&self, op: OpCode, ip: usize) -> bool {
-        let inner_op = *self
+        let inner_op = self
             .read_op(ip)
             .expect("Read from wrong instruction addres");
         op_comp(op, inner_op)
@@ -71,12 +124,45 @@ impl Chunk {
         }
     }
 
-    pub fn debug_ops_dump(&self) {
-        println!("\t OPS: {:?}", self.ops);
-        println!("\t CONSTS: {:?}", self.consts);
+    /// The ops and constants on two lines, for debugging the compiler
+    pub fn debug_ops_dump(&self) -> String {
+        let ops: Vec<OpCode> = self.instructions().map(|(_, op)| op).collect();
+        format!("\t OPS: {:?}\n\t CONSTS: {:?}\n", ops, self.consts)
+    }
+
+    /// Source line of the op at `ip`, 0 if there is no op there
+    pub fn get_line_num(&self, ip: usize) -> usize {
+        self.get_pos(ip).map_or(0, |pos| pos.line as usize)
+    }
+
+    pub fn get_pos(&self, ip: usize) -> Option<SourcePos> {
+        self.source_map.get(ip)
+    }
+
+    /// Record a local that lives from the next op on, returns its index for [`Chunk::close_local`]
+    pub fn open_local(&mut self, name: String, slot: u8) -> usize {
+        self.locals.push(DebugLocal {
+            name,
+            slot,
+            start: self.count(),
+            end: usize::MAX,
+        });
+        self.locals.len() - 1
+    }
+
+    /// The local goes out of scope at the next op
+    pub fn close_local(&mut self, idx: usize) {
+        self.locals[idx].end = self.count();
     }
 
-    pub fn get_line_num(&self, num: usize) -> usize {
-        self.line_nums[num]
+    /// Locals alive before the op at `ip` runs, ordered by slot
+    pub fn locals_at(&self, ip: usize) -> Vec<&DebugLocal> {
+        let mut live: Vec<&DebugLocal> = self
+            .locals
+            .iter()
+            .filter(|l| l.start <= ip && ip < l.end)
+            .collect();
+        live.sort_by_key(|l| l.slot);
+        live
     }
 }
@@ -1,13 +1,20 @@
 mod chunk;
+pub mod loxc;
+mod native;
+mod source_map;
 mod stack;
 mod value;
 mod var_store;
+mod verify;
 
 pub use value::Value;
 
-pub use chunk::Chunk;
+pub use chunk::{Chunk, DebugLocal};
+pub use native::{Native, NativeError, NativeFn};
+pub use source_map::{SourceMap, SourcePos};
 pub use stack::Stack;
 pub use var_store::VarStore;
+pub use verify::VerifyError;
 
 #[cfg(test)]
 mod tests {
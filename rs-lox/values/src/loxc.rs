@@ -0,0 +1,298 @@
+//! `.loxc` files, a compiled [`Chunk`] on disk.
+//!
+//! Layout, all numbers little endian:
+//!
+//! ```text
+//! magic    b"LOXC"
+//! version  u16
+//! consts   u32 count, then a tag byte and the payload of every value
+//! code     u32 size, then the encoded ops as the chunk holds them
+//! lines    u32 count, then offset u32, line u32, col u32, len u32 of every source map run
+//! locals   u32 count, then name, slot u8, start u32, end u32 of every debug local
+//! checksum u32, FNV-1a of everything before it
+//! ```
+//!
+//! Strings are a u32 length followed by the bytes.
+use thiserror::Error;
+
+use crate::{Chunk, DebugLocal, SourcePos, Value};
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+/// Bump when the layout changes, old files are refused instead of misread
+pub const VERSION: u16 = 3;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LoadError {
+    #[error("not a compiled lox file")]
+    BadMagic,
+    #[error("compiled with format version {0}, this build reads version {VERSION}")]
+    Version(u16),
+    #[error("checksum mismatch, the file is corrupt")]
+    Checksum,
+    #[error("corrupt file: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SaveError {
+    #[error("constant {0} is a {1}, only literals can be saved")]
+    Unsupported(usize, &'static str),
+}
+
+const TAG_NIL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+/// Does this look like a `.loxc` file, regardless of version
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Chunk {
+    /// The `.loxc` form of the chunk. The compiler only puts literals in the constant pool,
+    /// chunks made some other way may hold values that have no file form
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SaveError> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        put_u32(&mut out, self.consts.len());
+        for (idx, val) in self.consts.iter().enumerate() {
+            match val {
+                Value::Nil => out.push(TAG_NIL),
+                Value::Bool(b) => out.extend_from_slice(&[TAG_BOOL, *b as u8]),
+                Value::Int(i) => {
+                    out.push(TAG_INT);
+                    out.extend_from_slice(&i.to_le_bytes());
+                }
+                Value::Float(f) => {
+                    out.push(TAG_FLOAT);
+                    out.extend_from_slice(&f.to_le_bytes());
+                }
+                Value::String(s) => {
+                    out.push(TAG_STRING);
+                    put_str(&mut out, s);
+                }
+                Value::List(_) => return Err(SaveError::Unsupported(idx, "list")),
+                Value::Native(_) => return Err(SaveError::Unsupported(idx, "native function")),
+            }
+        }
+
+        put_u32(&mut out, self.count());
+        out.extend_from_slice(&self.code);
+        put_u32(&mut out, self.source_map.runs().count());
+        for (offset, pos) in self.source_map.runs() {
+            put_u32(&mut out, offset);
+            put_u32(&mut out, pos.line as usize);
+            put_u32(&mut out, pos.col as usize);
+            put_u32(&mut out, pos.len as usize);
+        }
+
+        put_u32(&mut out, self.locals.len());
+        for local in self.locals.iter() {
+            put_str(&mut out, &local.name);
+            out.push(local.slot);
+            put_u32(&mut out, local.start);
+            // locals that are never closed live until the end
+            put_u32(&mut out, local.end.min(u32::MAX as usize));
+        }
+
+        let sum = checksum(&out);
+        out.extend_from_slice(&sum.to_le_bytes());
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Chunk, LoadError> {
+        if !is_compiled(bytes) {
+            return Err(LoadError::BadMagic);
+        }
+        let mut r = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+        let version = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(LoadError::Version(version));
+        }
+        let (body, sum) = bytes.split_at(bytes.len().saturating_sub(4).max(r.pos));
+        if sum.len() != 4 {
+            return Err(LoadError::Corrupt("missing checksum".to_string()));
+        }
+        if checksum(body) != u32::from_le_bytes(sum.try_into().unwrap()) {
+            return Err(LoadError::Checksum);
+        }
+        r.bytes = body;
+
+        let mut chunk = Chunk::new();
+        for _ in 0..r.u32()? {
+            let val = match r.u8()? {
+                TAG_NIL => Value::Nil,
+                TAG_BOOL => Value::Bool(r.u8()? != 0),
+                TAG_INT => Value::Int(i32::from_le_bytes(r.take(4)?.try_into().unwrap())),
+                TAG_FLOAT => Value::Float(f32::from_le_bytes(r.take(4)?.try_into().unwrap())),
+                TAG_STRING => Value::String(r.string()?),
+                tag => return Err(LoadError::Corrupt(format!("unknown value tag {}", tag))),
+            };
+            chunk.consts.push(val);
+        }
+
+        // the ops are checked by the verifier before they run
+        let count = r.u32()?;
+        chunk.code = r.take(count)?.to_vec();
+        let mut runs = vec![];
+        for _ in 0..r.u32()? {
+            let offset = r.u32()?;
+            let pos = SourcePos::new(r.u32()? as u32, r.u32()? as u32, r.u32()? as u32);
+            runs.push((offset, pos));
+        }
+        for (i, (offset, pos)) in runs.iter().enumerate() {
+            let end = runs.get(i + 1).map_or(count, |(next, _)| *next);
+            let expected = chunk.source_map.size();
+            if *offset != expected || end <= *offset {
+                return Err(LoadError::Corrupt(format!(
+                    "source map run at {} should start at {}",
+                    offset, expected
+                )));
+            }
+            chunk.source_map.push(end - offset, *pos);
+        }
+
+        for _ in 0..r.u32()? {
+            let name = r.string()?;
+            let slot = r.u8()?;
+            let start = r.u32()?;
+            let end = match r.u32()? {
+                end if end == u32::MAX as usize => usize::MAX,
+                end => end,
+            };
+            chunk.locals.push(DebugLocal {
+                name,
+                slot,
+                start,
+                end,
+            });
+        }
+
+        if r.pos != r.bytes.len() {
+            return Err(LoadError::Corrupt("trailing bytes".to_string()));
+        }
+        Ok(chunk)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let res = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| LoadError::Corrupt("unexpected end of file".to_string()))?;
+        self.pos += len;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.u32()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| LoadError::Corrupt("string is not utf-8".to_string()))
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// 32 bit FNV-1a
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, b| {
+        (hash ^ *b as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::OpCode;
+
+    fn sample() -> Chunk {
+        let mut chunk = Chunk::new();
+        let name = chunk.add_const(Value::String("a".to_string())) as u8;
+        let one = chunk.add_const(Value::Float(1.5)) as u8;
+        chunk.add_const(Value::Int(-3));
+        chunk.add_const(Value::Bool(true));
+        chunk.add_const(Value::Nil);
+        chunk.add_op(OpCode::CONSTANT(one), 1);
+        chunk.add_op(OpCode::DEFINE_GLOBAL(name), 1);
+        let local = chunk.open_local("b".to_string(), 0);
+        chunk.add_op(OpCode::JUMP(7), 2);
+        chunk.close_local(local);
+        chunk.open_local("c".to_string(), 1);
+        chunk.add_op(OpCode::RETURN, 3);
+        chunk
+    }
+
+    #[test]
+    fn round_trip() {
+        let chunk = sample();
+        let bytes = chunk.to_bytes().unwrap();
+        assert!(is_compiled(&bytes));
+        let loaded = Chunk::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", chunk));
+    }
+
+    #[test]
+    fn refuses_bad_files() {
+        let bytes = sample().to_bytes().unwrap();
+        assert_eq!(
+            Chunk::from_bytes(b"print 1;").unwrap_err(),
+            LoadError::BadMagic
+        );
+
+        let mut newer = bytes.clone();
+        newer[4] = VERSION as u8 + 1;
+        assert_eq!(
+            Chunk::from_bytes(&newer).unwrap_err(),
+            LoadError::Version(VERSION + 1)
+        );
+
+        let mut flipped = bytes.clone();
+        flipped[12] ^= 0xff;
+        assert_eq!(
+            Chunk::from_bytes(&flipped).unwrap_err(),
+            LoadError::Checksum
+        );
+
+        assert!(matches!(
+            Chunk::from_bytes(&bytes[..7]),
+            Err(LoadError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_values_without_a_file_form() {
+        let mut chunk = sample();
+        chunk.add_const(Value::List(vec![Value::Nil]));
+        assert_eq!(
+            chunk.to_bytes().unwrap_err(),
+            SaveError::Unsupported(5, "list")
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Functions written in rust that lox code can call. The VM keeps the registry of them,
+//! here is only what a native looks like as a [`Value`].
+use std::fmt;
+use std::io::Write;
+
+use thiserror::Error;
+
+use crate::Value;
+
+/// Gets the program's output sink and the arguments, the VM already checked there are
+/// `arity` of them
+pub type NativeFn = fn(&mut dyn Write, &[Value]) -> Result<Value, NativeError>;
+
+#[derive(Debug, Error)]
+pub enum NativeError {
+    /// argument `arg` (0 based) has the wrong type
+    #[error("argument {} must be {expected}, got {got:?}", arg + 1)]
+    Type {
+        arg: usize,
+        expected: &'static str,
+        got: Value,
+    },
+    #[error("index {index} is out of range, the length is {len}")]
+    OutOfRange { index: usize, len: usize },
+    #[error("{0}")]
+    Failed(String),
+    /// not an error, the program asked to stop with this exit code
+    #[error("exit({0})")]
+    Exit(i32),
+}
+
+impl NativeError {
+    pub fn type_of(arg: usize, expected: &'static str, got: &Value) -> Self {
+        Self::Type {
+            arg,
+            expected,
+            got: got.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Native {
+    pub name: &'static str,
+    pub arity: u8,
+    pub func: NativeFn,
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
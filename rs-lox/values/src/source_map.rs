@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// Where an op came from: the expression (or statement) it was compiled from.
+/// `col` is 1 based, a `col` of 0 means only the line is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourcePos {
+    pub line: u32,
+    pub col: u32,
+    /// length of the expression in chars
+    pub len: u32,
+}
+
+impl SourcePos {
+    pub fn new(line: u32, col: u32, len: u32) -> Self {
+        Self { line, col, len }
+    }
+
+    /// A position that only knows the line
+    pub fn line(line: u32) -> Self {
+        Self::new(line, 0, 0)
+    }
+}
+
+impl fmt::Display for SourcePos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.col {
+            0 => write!(f, "line {}", self.line),
+            col => write!(f, "line {}:{}", self.line, col),
+        }
+    }
+}
+
+/// Source position of every byte of code, run length encoded.
+/// Ops of one expression sit next to each other, so a run starts only where the position changes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    /// code offset where the run starts, and the position of all the code up to the next run
+    runs: Vec<(u32, SourcePos)>,
+    /// how many bytes of code are covered
+    size: usize,
+}
+
+impl SourceMap {
+    /// The next `size` bytes of code come from `pos`
+    pub fn push(&mut self, size: usize, pos: SourcePos) {
+        if self.runs.last().map(|(_, last)| *last) != Some(pos) {
+            self.runs.push((self.size as u32, pos));
+        }
+        self.size += size;
+    }
+
+    /// Position of the code at `offset`, `None` past the end
+    pub fn get(&self, offset: usize) -> Option<SourcePos> {
+        if offset >= self.size {
+            return None;
+        }
+        let idx = self
+            .runs
+            .partition_point(|(start, _)| *start as usize <= offset);
+        self.runs.get(idx.checked_sub(1)?).map(|(_, pos)| *pos)
+    }
+
+    /// Bytes of code covered, should be the size of the chunk
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Every run as (code offset, position), in code order
+    pub fn runs(&self) -> impl Iterator<Item = (usize, SourcePos)> + '_ {
+        self.runs.iter().map(|(start, pos)| (*start as usize, *pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_are_merged() {
+        let mut map = SourceMap::default();
+        let a = SourcePos::new(1, 7, 5);
+        let b = SourcePos::new(2, 1, 12);
+        map.push(2, a);
+        map.push(1, a);
+        map.push(3, b);
+        map.push(1, a);
+
+        assert_eq!(map.runs().count(), 3);
+        assert_eq!(map.size(), 7);
+        let found: Vec<_> = (0..8).map(|ip| map.get(ip)).collect();
+        let expected = [a, a, a, b, b, b, a].map(Some);
+        assert_eq!(found[..7], expected);
+        assert_eq!(found[7], None);
+        assert_eq!(a.to_string(), "line 1:7");
+        assert_eq!(SourcePos::line(3).to_string(), "line 3");
+    }
+}
@@ -1,9 +1,10 @@
-use std::{borrow::Borrow, mem::MaybeUninit};
+use std::borrow::Borrow;
+use std::io::{self, Write};
 
 use crate::Value;
 use thiserror::Error;
 
-const STACK_MAX: usize = u8::MAX as usize + 1;
+pub(crate) const STACK_MAX: usize = u8::MAX as usize + 1;
 
 #[derive(Debug, Error)]
 pub enum StackError {
@@ -20,15 +21,8 @@ pub struct Stack {
 
 impl Stack {
     pub fn init() -> Self {
-        // SAFETY: init an array with non-copy value.
-        let empty_stack = unsafe {
-            let mut tmp = MaybeUninit::<[Value; STACK_MAX]>::uninit().assume_init();
-
-            for v in tmp.iter_mut() {
-                *v = Value::Nil;
-            }
-            tmp
-        };
+        // init an array with non-copy value.
+        let empty_stack: [Value; STACK_MAX] = std::array::from_fn(|_| Value::Nil);
 
         Self {
             stack: empty_stack,
@@ -47,7 +41,7 @@ impl Stack {
     }
 
     pub fn pop(&mut self) -> Result<Value, StackError> {
-        if self.top <= 0 {
+        if self.top == 0 {
             return Err(StackError::Underflow);
         }
 
@@ -65,19 +59,40 @@ impl Stack {
         Some(self.stack[self.top - 1].borrow())
     }
 
+    /// Value at `idx` from the bottom, if it's on the stack
+    pub fn get(&self, idx: usize) -> Option<&Value> {
+        self.stack[..self.top].get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.top
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top == 0
+    }
+
+    /// Drop everything above `len`
+    pub fn truncate(&mut self, len: usize) {
+        while self.top > len {
+            self.top -= 1;
+            self.stack[self.top] = Value::Nil;
+        }
+    }
+
     pub fn peek_at(&mut self, idx: usize) -> Option<&mut Value> {
         self.stack.get_mut(idx)
     }
 
-    pub fn show_stack(&self) {
-        print!(" [");
+    pub fn show_stack(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, " [")?;
         for (idx, v) in self.stack.iter().enumerate() {
             match v {
                 Value::Nil if idx >= self.top => continue,
-                e => print!(" {} ", *e),
+                e => write!(out, " {} ", *e)?,
             }
         }
-        println!("]")
+        writeln!(out, "]")
     }
 }
 
@@ -2,6 +2,8 @@ use std::{fmt, str::FromStr};
 
 use lang::OpCode;
 
+use crate::Native;
+
 #[derive(Clone, Debug)]
 #[repr(u8)]
 /// Represents dynamic values in Lox.
@@ -13,7 +15,10 @@ pub enum Value {
     Int(i32),
     Float(f32),
     String(String), // String(HeapPtr),
-                    // Obj(HeapObj)
+    // Obj(HeapObj)
+    /// read only for now, only the runtime makes them (like `args`)
+    List(Vec<Value>),
+    Native(Native),
 }
 
 impl From<f32> for Value {
@@ -197,6 +202,17 @@ impl fmt::Display for Value {
             Value::Bool(v) => write!(f, "{}", v),
             Value::String(v) => write!(f, "{}", v),
             Value::Nil => write!(f, "Nil"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Native(native) => write!(f, "{:?}", native),
         }
     }
 }
@@ -7,6 +7,12 @@ pub struct VarStore {
     store: HashMap<String, Value>,
 }
 
+impl Default for VarStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VarStore {
     pub fn new() -> Self {
         let store = HashMap::new();
@@ -21,6 +27,10 @@ impl VarStore {
         self.store.get(ident_)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.store.iter()
+    }
+
     pub fn contains(&self, ident_: &str) -> bool {
         self.store.contains_key(ident_)
     }
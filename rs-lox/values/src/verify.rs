@@ -0,0 +1,221 @@
+//! Static checks on a [`Chunk`] before the VM runs it.
+//!
+//! The VM trusts the bytecode, a bad jump or constant index would panic in the middle of a run.
+//! Chunks from the compiler are fine, the verifier is there for the ones loaded from disk or
+//! produced by other tools.
+use lang::OpCode;
+use thiserror::Error;
+
+use crate::stack::STACK_MAX;
+use crate::{Chunk, Value};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum VerifyError {
+    #[error("empty chunk")]
+    Empty,
+    #[error("chunk doesn't end with RETURN")]
+    NoReturn,
+    #[error("{0} bytes of code but the source map covers {1}")]
+    LineTable(usize, usize),
+    #[error("@{0}: not a valid op")]
+    BadOp(usize),
+    #[error("@{0}: jump to {1} is not the start of an op")]
+    BadJump(usize, usize),
+    #[error("@{0}: constant {1} doesn't exist")]
+    BadConstant(usize, u8),
+    #[error("@{0}: constant {1} is not a variable name")]
+    BadName(usize, u8),
+    #[error("@{0}: local slot {1} is not on the stack")]
+    BadLocal(usize, u8),
+    #[error("@{0}: stack underflow")]
+    Underflow(usize),
+    #[error("@{0}: stack overflow")]
+    Overflow(usize),
+    #[error("@{0}: stack depth is {1} on one path and {2} on another")]
+    Inconsistent(usize, usize, usize),
+}
+
+impl Chunk {
+    /// Verify a chunk that starts on an empty stack
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        self.verify_from(0)
+    }
+
+    /// Verify a chunk that starts with `depth` values already on the stack
+    pub fn verify_from(&self, depth: usize) -> Result<(), VerifyError> {
+        let count = self.count();
+        if count == 0 {
+            return Err(VerifyError::Empty);
+        }
+        if self.source_map.size() != count {
+            return Err(VerifyError::LineTable(count, self.source_map.size()));
+        }
+        // where every op starts, jumps may only land there
+        let mut starts = vec![false; count];
+        let mut ip = 0;
+        let mut last = None;
+        while ip < count {
+            let (op, len) = self.decode_op(ip).ok_or(VerifyError::BadOp(ip))?;
+            starts[ip] = true;
+            last = Some(op);
+            ip += len;
+        }
+        if !matches!(last, Some(OpCode::RETURN)) {
+            return Err(VerifyError::NoReturn);
+        }
+
+        // stack depth before every op, found by walking all the paths
+        let mut depths: Vec<Option<usize>> = vec![None; count];
+        let mut todo = vec![(0, depth)];
+        while let Some((ip, depth)) = todo.pop() {
+            match depths[ip] {
+                Some(seen) if seen == depth => continue,
+                Some(seen) => return Err(VerifyError::Inconsistent(ip, seen, depth)),
+                None => depths[ip] = Some(depth),
+            }
+            let (op, len) = self.decode_op(ip).ok_or(VerifyError::BadOp(ip))?;
+            self.check_operand(ip, op, depth)?;
+
+            let (pops, pushes) = stack_effect(op);
+            if depth < pops {
+                return Err(VerifyError::Underflow(ip));
+            }
+            let after = depth - pops + pushes;
+            if after > STACK_MAX {
+                return Err(VerifyError::Overflow(ip));
+            }
+
+            match op {
+                OpCode::RETURN => {}
+                OpCode::JUMP(target) => todo.push((jump_target(&starts, ip, target)?, after)),
+                OpCode::JUMP_IF_FALSE(target) => {
+                    todo.push((jump_target(&starts, ip, target)?, after));
+                    todo.push((ip + len, after));
+                }
+                // the last op is a RETURN, so there is always a next one
+                _ => todo.push((ip + len, after)),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_operand(&self, ip: usize, op: OpCode, depth: usize) -> Result<(), VerifyError> {
+        use OpCode::*;
+        match op {
+            CONSTANT(idx) if idx as usize >= self.consts.len() => {
+                Err(VerifyError::BadConstant(ip, idx))
+            }
+            DEFINE_GLOBAL(idx) | GET_GLOBAL(idx) | SET_GLOBAL(idx) | INVOKE(idx, _) => {
+                match self.consts.get(idx as usize) {
+                    None => Err(VerifyError::BadConstant(ip, idx)),
+                    Some(Value::String(_)) => Ok(()),
+                    Some(_) => Err(VerifyError::BadName(ip, idx)),
+                }
+            }
+            GET_LOCAL(slot) | SET_LOCAL(slot) if slot as usize >= depth => {
+                Err(VerifyError::BadLocal(ip, slot))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn jump_target(starts: &[bool], ip: usize, target: u16) -> Result<usize, VerifyError> {
+    let target = target as usize;
+    if !starts.get(target).copied().unwrap_or(false) {
+        return Err(VerifyError::BadJump(ip, target));
+    }
+    Ok(target)
+}
+
+/// How many values the op takes off the stack and how many it leaves
+fn stack_effect(op: OpCode) -> (usize, usize) {
+    use OpCode::*;
+    match op {
+        RETURN | JUMP(_) => (0, 0),
+        CONSTANT(_) | NIL | TRUE | FALSE | GET_GLOBAL(_) | GET_LOCAL(_) => (0, 1),
+        NEGATE | NOT => (1, 1),
+        EQUAL | LESS | GREATER | ADD | SUB | MUL | DIV | AND | OR | INDEX => (2, 1),
+        PRINT | POP | DEFINE_GLOBAL(_) | ASSERT => (1, 0),
+        // assignments and conditional jumps only peek
+        SET_GLOBAL(_) | SET_LOCAL(_) | JUMP_IF_FALSE(_) => (1, 1),
+        ASSERT_EQ => (2, 0),
+        // the callee and its arguments make way for the result
+        CALL(argc) | INVOKE(_, argc) => (argc as usize + 1, 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(ops: &[OpCode]) -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.add_const(Value::Float(1.0));
+        chunk.add_const(Value::String("a".to_string()));
+        for op in ops {
+            chunk.add_op(*op, 1);
+        }
+        chunk
+    }
+
+    #[test]
+    fn accepts_good_code() {
+        use OpCode::*;
+        // { var a = 1; if (a > 1) print a; }
+        let ops = [
+            CONSTANT(0),
+            GET_LOCAL(0),
+            CONSTANT(0),
+            GREATER,
+            JUMP_IF_FALSE(17),
+            POP,
+            GET_LOCAL(0),
+            PRINT,
+            JUMP(18),
+            POP,
+            POP,
+            RETURN,
+        ];
+        assert_eq!(chunk(&ops).verify(), Ok(()));
+        // an expression evaluated in a paused program, with two locals on the stack
+        assert_eq!(chunk(&[GET_LOCAL(1), RETURN]).verify_from(2), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_code() {
+        use OpCode::*;
+        let cases = [
+            (vec![], VerifyError::Empty),
+            (vec![NIL, PRINT], VerifyError::NoReturn),
+            (vec![JUMP(9), RETURN], VerifyError::BadJump(0, 9)),
+            (vec![NIL, JUMP(2), RETURN], VerifyError::BadJump(1, 2)),
+            (
+                vec![CONSTANT(2), POP, RETURN],
+                VerifyError::BadConstant(0, 2),
+            ),
+            (vec![GET_GLOBAL(0), POP, RETURN], VerifyError::BadName(0, 0)),
+            (vec![NIL, GET_LOCAL(1), RETURN], VerifyError::BadLocal(1, 1)),
+            (vec![NIL, ADD, RETURN], VerifyError::Underflow(1)),
+            (
+                vec![TRUE, JUMP_IF_FALSE(8), NIL, JUMP(8), RETURN],
+                VerifyError::Inconsistent(8, 2, 1),
+            ),
+        ];
+        for (ops, err) in cases {
+            assert_eq!(chunk(&ops).verify(), Err(err), "{:?}", ops);
+        }
+
+        let mut deep = vec![NIL; STACK_MAX + 1];
+        deep.push(RETURN);
+        assert_eq!(chunk(&deep).verify(), Err(VerifyError::Overflow(STACK_MAX)));
+
+        let mut garbage = chunk(&[NIL, RETURN]);
+        garbage.code[0] = 0xee;
+        assert_eq!(garbage.verify(), Err(VerifyError::BadOp(0)));
+
+        let mut no_lines = chunk(&[RETURN]);
+        no_lines.source_map = Default::default();
+        assert_eq!(no_lines.verify(), Err(VerifyError::LineTable(1, 0)));
+    }
+}